@@ -0,0 +1,66 @@
+//! Plugs a custom [`Transcriber`] into the pipeline. This one only reports
+//! the size of the converted audio.
+//!
+//! ```sh
+//! cargo run --example custom_transcriber -- <url or file>
+//! ```
+
+use anyhow::Context;
+use async_trait::async_trait;
+use std::sync::Arc;
+use yt_dlts::{
+    batch,
+    progress::Observer,
+    transcript::{Detail, Transcript},
+    Pipeline, Transcriber,
+};
+
+struct Sizer;
+
+#[async_trait]
+impl Transcriber for Sizer {
+    fn name(&self) -> &str {
+        "sizer"
+    }
+
+    fn model(&self) -> &str {
+        "bytes"
+    }
+
+    fn needs_chunking(&self) -> bool {
+        false
+    }
+
+    async fn transcribe(
+        &self,
+        file_path: &str,
+        _detail: Detail,
+        _observer: Arc<dyn Observer>,
+    ) -> yt_dlts::Result<Transcript> {
+        let size = tokio::fs::metadata(file_path)
+            .await
+            .context("Failed to read audio file")?
+            .len();
+        Ok(Transcript {
+            text: format!("{} bytes of audio", size),
+            ..Default::default()
+        })
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let input = std::env::args()
+        .nth(1)
+        .context("Usage: custom_transcriber <url or file>")?;
+    let pipeline = Pipeline::builder(Arc::new(Sizer)).build()?;
+
+    for (i, item) in batch::resolve(&input).await?.iter().enumerate() {
+        println!(
+            "{}: {}",
+            item.title,
+            pipeline.transcribe(item, i + 1).await?.text
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,33 @@
+//! Downloads the audio of a video and converts it to 16 kHz FLAC, without
+//! transcribing it.
+//!
+//! ```sh
+//! cargo run --example extract_audio -- <url> audio.flac
+//! ```
+
+use anyhow::Context;
+use yt_dlts::{
+    convert_audio, download_audio,
+    profile::{Encoding, Profile},
+    progress::Reporter,
+};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let url = args.next().context("Usage: extract_audio <url> <output>")?;
+    let output = args.next().context("Usage: extract_audio <url> <output>")?;
+
+    let workdir = tempfile::tempdir()?;
+    let download = workdir.path().join("audio");
+    let download = download.to_str().context("Invalid temporary path")?;
+    let tracker = Reporter::new(None).item(&url, &url, 1);
+
+    let info = download_audio(&url, download, None, &tracker).await?;
+    let encoding = Encoding::new(Profile::Flac16k, None, None, &[])?;
+    convert_audio(download, &output, &encoding, None, &tracker).await?;
+    tracker.done();
+
+    println!("Saved {} to {}", info["title"], output);
+    Ok(())
+}
@@ -0,0 +1,26 @@
+//! Transcribes a video, playlist or local file with Groq.
+//!
+//! ```sh
+//! GROQ_API_KEY=... cargo run --example transcribe -- <url or file>
+//! ```
+
+use anyhow::Context;
+use std::sync::Arc;
+use yt_dlts::{backend::OpenAiCompatible, batch, progress::Reporter, Pipeline};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let input = std::env::args()
+        .nth(1)
+        .context("Usage: transcribe <url or file>")?;
+    let api_key = std::env::var("GROQ_API_KEY").context("GROQ_API_KEY not set")?;
+    let pipeline = Pipeline::builder(Arc::new(OpenAiCompatible::groq(api_key)))
+        .reporter(Reporter::new(None))
+        .build()?;
+
+    for (i, item) in batch::resolve(&input).await?.iter().enumerate() {
+        let transcript = pipeline.transcribe(item, i + 1).await?;
+        println!("# {}\n\n{}\n", item.title, transcript.text);
+    }
+    Ok(())
+}
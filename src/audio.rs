@@ -0,0 +1,82 @@
+use anyhow::Context;
+use serde_json::Value;
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::clip::Range;
+use crate::error::Result;
+use crate::profile::Encoding;
+use crate::progress::{self, Stage, Tracker};
+
+/// Downloads the audio of `url` and returns yt-dlp's info JSON for it.
+/// `range` limits the download to part of the video.
+pub async fn download_audio(
+    url: &str,
+    output_file: &str,
+    range: Option<&Range>,
+    tracker: &Tracker,
+) -> Result<Value> {
+    let mut command = Command::new("yt-dlp");
+    command
+        .args([
+            "-f",
+            "bestaudio",
+            "-N8",
+            "--dump-json",
+            "--no-simulate",
+            "-o",
+            output_file,
+        ])
+        .args(progress::YT_DLP_ARGS);
+    if let Some(range) = range {
+        command.args(["--download-sections", &range.download_section()]);
+    }
+    let child = command
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to execute yt-dlp")?;
+
+    tracker.start(Stage::Download);
+    let output = progress::watch(child, "yt-dlp", |line| tracker.yt_dlp_line(line)).await;
+    tracker.finish(Stage::Download);
+
+    Ok(serde_json::from_str(&output?).context("Failed to parse yt-dlp info JSON")?)
+}
+
+/// Converts `input_file` for upload. `seek` cuts a range out of inputs that
+/// were not already clipped while downloading.
+pub async fn convert_audio(
+    input_file: &str,
+    output_file: &str,
+    encoding: &Encoding,
+    seek: Option<&Range>,
+    tracker: &Tracker,
+) -> Result<()> {
+    let child = Command::new("ffmpeg")
+        .args(["-progress", "pipe:1", "-nostats"])
+        .args(seek.map(Range::ffmpeg_args).unwrap_or_default())
+        .args(["-i", input_file])
+        .args(encoding.ffmpeg_args())
+        .args(["-ac", "1", "-map", "0:a:", "-vn", output_file])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to execute ffmpeg")?;
+
+    tracker.start(Stage::Convert);
+    let mut duration = seek.and_then(Range::duration);
+    let output = progress::watch(child, "ffmpeg", |line| {
+        tracker.ffmpeg_line(line, &mut duration)
+    })
+    .await;
+    tracker.finish(Stage::Convert);
+
+    output?;
+    Ok(())
+}
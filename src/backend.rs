@@ -0,0 +1,474 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use reqwest::multipart::{Form, Part};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::io::AsyncRead;
+use tokio_util::io::ReaderStream;
+
+use crate::error::{ApiError, Result};
+use crate::profile::Profile;
+use crate::progress::Observer;
+use crate::retry;
+use crate::transcript::{Detail, Transcript};
+
+const GROQ_BASE_URL: &str = "https://api.groq.com/openai/v1";
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// How often the hosted services retry a request unless told otherwise.
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
+/// Size of the pieces audio is uploaded in, which sets how often upload
+/// progress is reported.
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Options forwarded to Whisper with every request.
+#[derive(Clone, Debug, Default)]
+pub struct RequestOptions {
+    /// Spoken language as an ISO-639-1 code; detected if `None`.
+    pub language: Option<String>,
+    /// Text that guides spelling and style.
+    pub prompt: Option<String>,
+    /// Sampling temperature between 0 and 1.
+    pub temperature: Option<f32>,
+    /// Translate the speech into English instead of transcribing it.
+    pub translate: bool,
+}
+
+impl RequestOptions {
+    /// Identifies these options in cache keys.
+    pub fn cache_key(&self) -> String {
+        format!(
+            "language={}|prompt={}|temperature={}|translate={}",
+            self.language.as_deref().unwrap_or_default(),
+            self.prompt.as_deref().unwrap_or_default(),
+            self.temperature.map(|t| t.to_string()).unwrap_or_default(),
+            self.translate
+        )
+    }
+}
+
+/// Something that can turn an audio file into a transcript.
+#[async_trait]
+pub trait Transcriber: Send + Sync {
+    /// Short backend name, e.g. `groq`.
+    fn name(&self) -> &str;
+
+    /// Model used for transcription.
+    fn model(&self) -> &str;
+
+    /// Conversion profile used when `--profile` is not given.
+    fn default_profile(&self) -> Profile {
+        Profile::OpusLow
+    }
+
+    /// Whether long audio has to be split to fit the upload limit.
+    fn needs_chunking(&self) -> bool {
+        true
+    }
+
+    /// Settings besides the name and model that change the transcript, for
+    /// cache keys.
+    fn cache_key(&self) -> String {
+        String::new()
+    }
+
+    /// Transcribes the audio in `file_path`, telling `observer` how the
+    /// upload goes and about retries.
+    async fn transcribe(
+        &self,
+        file_path: &str,
+        detail: Detail,
+        observer: Arc<dyn Observer>,
+    ) -> Result<Transcript>;
+
+    /// Transcribes audio as it is being encoded, for `--stream`. `file_name`
+    /// only tells the service the container format.
+    async fn transcribe_stream(
+        &self,
+        _audio: Box<dyn AsyncRead + Send + Unpin>,
+        _file_name: &str,
+        _detail: Detail,
+        _observer: Arc<dyn Observer>,
+    ) -> Result<Transcript> {
+        Err(anyhow::anyhow!(
+            "The {} backend cannot transcribe streamed audio",
+            self.name()
+        )
+        .into())
+    }
+}
+
+/// A server implementing OpenAI's `/audio/transcriptions` and
+/// `/audio/translations` endpoints.
+pub struct OpenAiCompatible {
+    name: &'static str,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    max_retries: u32,
+    request: RequestOptions,
+    default_profile: Profile,
+    client: reqwest::Client,
+}
+
+impl OpenAiCompatible {
+    /// A self-hosted server. These vary in the codecs they accept, so audio
+    /// defaults to lossless FLAC, which still keeps uploads reasonably small.
+    pub fn new(
+        name: &'static str,
+        base_url: String,
+        api_key: Option<String>,
+        model: String,
+        max_retries: u32,
+        request: RequestOptions,
+    ) -> Self {
+        OpenAiCompatible {
+            name,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+            max_retries,
+            request,
+            default_profile: Profile::Flac16k,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Groq's hosted Whisper with its recommended model.
+    pub fn groq(api_key: String) -> Self {
+        OpenAiCompatible::new(
+            "groq",
+            GROQ_BASE_URL.to_string(),
+            Some(api_key),
+            "whisper-large-v3".to_string(),
+            DEFAULT_MAX_RETRIES,
+            RequestOptions::default(),
+        )
+        .with_default_profile(Profile::OpusLow)
+    }
+
+    /// OpenAI's hosted Whisper.
+    pub fn openai(api_key: String) -> Self {
+        OpenAiCompatible::new(
+            "openai",
+            OPENAI_BASE_URL.to_string(),
+            Some(api_key),
+            "whisper-1".to_string(),
+            DEFAULT_MAX_RETRIES,
+            RequestOptions::default(),
+        )
+        .with_default_profile(Profile::OpusLow)
+    }
+
+    /// Sends requests to `base_url` instead, e.g. through a proxy.
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Transcribes with `model` instead of the service's recommended one.
+    pub fn with_model(mut self, model: String) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// How often a rate-limited or failed request is retried (5).
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Options sent with every request (none).
+    pub fn with_request(mut self, request: RequestOptions) -> Self {
+        self.request = request;
+        self
+    }
+
+    /// Conversion profile used when none is chosen.
+    pub fn with_default_profile(mut self, profile: Profile) -> Self {
+        self.default_profile = profile;
+        self
+    }
+
+    fn url(&self) -> String {
+        let endpoint = if self.request.translate {
+            "translations"
+        } else {
+            "transcriptions"
+        };
+        format!("{}/audio/{}", self.base_url, endpoint)
+    }
+
+    /// Form fields sent along with the audio.
+    fn fields(&self, detail: Detail) -> Vec<(&'static str, String)> {
+        let response_format = if detail >= Detail::Segments {
+            "verbose_json"
+        } else {
+            "json"
+        };
+        let mut fields = vec![
+            ("model", self.model.clone()),
+            ("response_format", response_format.to_string()),
+        ];
+        if detail == Detail::Words {
+            // Asking for words alone would drop the segments
+            fields.push(("timestamp_granularities[]", "segment".to_string()));
+            fields.push(("timestamp_granularities[]", "word".to_string()));
+        }
+        if let Some(language) = &self.request.language {
+            fields.push(("language", language.clone()));
+        }
+        if let Some(prompt) = &self.request.prompt {
+            fields.push(("prompt", prompt.clone()));
+        }
+        if let Some(temperature) = self.request.temperature {
+            fields.push(("temperature", temperature.to_string()));
+        }
+        fields
+    }
+
+    /// The multipart request for one upload of `file`.
+    fn request(&self, file: Part, detail: Detail) -> reqwest::RequestBuilder {
+        let form = self
+            .fields(detail)
+            .into_iter()
+            .fold(Form::new().part("file", file), |form, (name, value)| {
+                form.text(name, value)
+            });
+
+        let mut request = self.client.post(self.url()).multipart(form);
+        if let Some(api_key) = &self.api_key {
+            request = request.header("Authorization", format!("Bearer {}", api_key));
+        }
+        request
+    }
+}
+
+/// Reports the bytes passing through `stream` to `observer`.
+fn observe<S, B>(
+    stream: S,
+    total: Option<u64>,
+    observer: Arc<dyn Observer>,
+) -> impl Stream<Item = std::io::Result<B>>
+where
+    S: Stream<Item = std::io::Result<B>>,
+    B: AsRef<[u8]>,
+{
+    let mut sent = 0;
+    stream.map(move |chunk| {
+        if let Ok(bytes) = &chunk {
+            sent += bytes.as_ref().len() as u64;
+            observer.uploaded(sent, total);
+        }
+        chunk
+    })
+}
+
+async fn parse_response(response: reqwest::Response) -> Result<Transcript> {
+    let json: Value = response
+        .json()
+        .await
+        .context("Failed to parse JSON response")?;
+    Ok(serde_json::from_value(json).context("Failed to extract transcript from JSON")?)
+}
+
+#[async_trait]
+impl Transcriber for OpenAiCompatible {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn default_profile(&self) -> Profile {
+        self.default_profile
+    }
+
+    fn cache_key(&self) -> String {
+        format!("base_url={}|{}", self.base_url, self.request.cache_key())
+    }
+
+    async fn transcribe(
+        &self,
+        file_path: &str,
+        detail: Detail,
+        observer: Arc<dyn Observer>,
+    ) -> Result<Transcript> {
+        let file_bytes = tokio::fs::read(file_path)
+            .await
+            .context("Failed to read audio file")?;
+        let length = file_bytes.len() as u64;
+
+        let mut attempt = 0;
+        loop {
+            // A multipart form can only be sent once, so build it per attempt
+            let pieces: Vec<std::io::Result<Vec<u8>>> = file_bytes
+                .chunks(UPLOAD_CHUNK_SIZE)
+                .map(|piece| Ok(piece.to_vec()))
+                .collect();
+            let body = reqwest::Body::wrap_stream(observe(
+                futures::stream::iter(pieces),
+                Some(length),
+                observer.clone(),
+            ));
+            let file_part = Part::stream_with_length(body, length).file_name(file_path.to_string());
+            let request = self.request(file_part, detail);
+
+            let delay = match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    return parse_response(response).await;
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    if !retry::is_retryable(status) || attempt >= self.max_retries {
+                        let body = response.text().await.unwrap_or_default();
+                        return Err(ApiError::from_response(status, &body).into());
+                    }
+                    observer.warning(&format!("Transcription request failed with {}", status));
+                    retry::delay(status, response.headers(), attempt)
+                }
+                Err(e) => {
+                    if attempt >= self.max_retries {
+                        return Err(anyhow::Error::new(e)
+                            .context("Failed to send request")
+                            .into());
+                    }
+                    observer.warning(&format!("Failed to send request: {}", e));
+                    retry::backoff(attempt)
+                }
+            };
+
+            attempt += 1;
+            observer.warning(&format!(
+                "Retrying in {:.1}s ({}/{})",
+                delay.as_secs_f64(),
+                attempt,
+                self.max_retries
+            ));
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn transcribe_stream(
+        &self,
+        audio: Box<dyn AsyncRead + Send + Unpin>,
+        file_name: &str,
+        detail: Detail,
+        observer: Arc<dyn Observer>,
+    ) -> Result<Transcript> {
+        let body = reqwest::Body::wrap_stream(observe(ReaderStream::new(audio), None, observer));
+        let file_part = Part::stream(body).file_name(file_name.to_string());
+
+        // The audio is gone once sent, so there is nothing to retry with
+        let response = self
+            .request(file_part, detail)
+            .send()
+            .await
+            .context("Failed to send request")?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ApiError::from_response(status, &body).into());
+        }
+        parse_response(response).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(base_url: &str, request: RequestOptions) -> OpenAiCompatible {
+        OpenAiCompatible::new(
+            "compatible",
+            base_url.to_string(),
+            None,
+            "whisper-1".to_string(),
+            0,
+            request,
+        )
+    }
+
+    #[test]
+    fn transcriptions_send_only_the_given_options() {
+        let transcriptions = server("http://localhost:8080/v1/", RequestOptions::default());
+        assert_eq!(
+            transcriptions.url(),
+            "http://localhost:8080/v1/audio/transcriptions"
+        );
+        assert_eq!(
+            transcriptions.fields(Detail::Text),
+            [
+                ("model", "whisper-1".to_string()),
+                ("response_format", "json".to_string()),
+            ]
+        );
+
+        let request = RequestOptions {
+            prompt: Some("Rust, Tokio".to_string()),
+            temperature: Some(0.5),
+            ..Default::default()
+        };
+        let fields = server("http://localhost:8080/v1", request).fields(Detail::Text);
+        assert!(fields.contains(&("prompt", "Rust, Tokio".to_string())));
+        assert!(fields.contains(&("temperature", "0.5".to_string())));
+        assert!(!fields.iter().any(|(name, _)| *name == "language"));
+    }
+
+    #[test]
+    fn word_timestamps_keep_the_segments() {
+        let server = server("http://localhost:8080/v1", RequestOptions::default());
+        let granularities = |detail| {
+            server
+                .fields(detail)
+                .into_iter()
+                .filter(|(name, _)| *name == "timestamp_granularities[]")
+                .map(|(_, value)| value)
+                .collect::<Vec<_>>()
+        };
+        assert!(granularities(Detail::Segments).is_empty());
+        assert_eq!(granularities(Detail::Words), ["segment", "word"]);
+        assert!(server
+            .fields(Detail::Segments)
+            .contains(&("response_format", "verbose_json".to_string())));
+        assert!(server
+            .fields(Detail::Words)
+            .contains(&("response_format", "verbose_json".to_string())));
+    }
+
+    #[test]
+    fn translations_keep_the_spoken_language() {
+        let request = RequestOptions {
+            language: Some("de".to_string()),
+            translate: true,
+            ..Default::default()
+        };
+        let server = server("http://localhost:8080/v1", request);
+        assert_eq!(server.url(), "http://localhost:8080/v1/audio/translations");
+        assert!(server
+            .fields(Detail::Text)
+            .contains(&("language", "de".to_string())));
+    }
+
+    #[test]
+    fn cache_keys_include_the_server_and_request_options() {
+        let request = RequestOptions {
+            language: Some("de".to_string()),
+            prompt: Some("Rust".to_string()),
+            temperature: Some(0.2),
+            translate: true,
+        };
+        assert_eq!(
+            server("http://localhost:8080/v1/", request).cache_key(),
+            "base_url=http://localhost:8080/v1|language=de|prompt=Rust|temperature=0.2|translate=true"
+        );
+        assert_ne!(
+            server("http://a/v1", RequestOptions::default()).cache_key(),
+            server("http://b/v1", RequestOptions::default()).cache_key()
+        );
+    }
+}
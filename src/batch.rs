@@ -0,0 +1,257 @@
+use anyhow::Context;
+use serde_json::Value;
+use std::path::Path;
+use tokio::process::Command;
+
+use crate::error::{ProcessError, Result};
+use crate::transcript::Format;
+
+/// Default `--output-template` used when transcribing more than one item.
+pub const DEFAULT_TEMPLATE: &str = "{title} [{id}].{ext}";
+
+/// File extensions treated as direct links to media rather than web pages.
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "aac", "flac", "m4a", "mkv", "mov", "mp3", "mp4", "oga", "ogg", "opus", "wav", "webm",
+];
+
+/// A single video to transcribe.
+#[derive(Clone, Debug)]
+pub struct Item {
+    pub url: String,
+    pub id: String,
+    pub title: String,
+    pub source: Source,
+}
+
+/// Where the media for an item comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// A page yt-dlp has to download the audio from.
+    YtDlp,
+    /// A local file or direct media link that ffmpeg can read as-is.
+    Direct,
+    /// Media piped into our standard input.
+    Stdin,
+}
+
+/// Reads a batch file with one URL per line. Blank lines and lines starting
+/// with `#` are ignored.
+pub async fn read_batch_file(path: &str) -> Result<Vec<String>> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .context("Failed to read batch file")?;
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Resolves an input into the items it refers to. `-` means standard input,
+/// local paths, `file://` URLs and direct media links go straight to ffmpeg,
+/// and everything else is handed to yt-dlp.
+pub async fn resolve(input: &str) -> Result<Vec<Item>> {
+    if input == "-" {
+        return Ok(vec![Item {
+            url: input.to_string(),
+            id: "stdin".to_string(),
+            title: "stdin".to_string(),
+            source: Source::Stdin,
+        }]);
+    }
+
+    if let Some(path) = input.strip_prefix("file://") {
+        let path = percent_decode(path);
+        if !Path::new(&path).exists() {
+            return Err(anyhow::anyhow!("File not found: {}", path).into());
+        }
+        return Ok(vec![direct_item(&path)]);
+    }
+
+    if Path::new(input).exists() || is_media_url(input) {
+        return Ok(vec![direct_item(input)]);
+    }
+
+    expand(input).await
+}
+
+fn direct_item(location: &str) -> Item {
+    let name = location
+        .rsplit(['/', '\\'])
+        .next()
+        .and_then(|name| name.split(['?', '#']).next())
+        .unwrap_or(location);
+    let stem = Path::new(name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(name);
+    Item {
+        url: location.to_string(),
+        id: stem.to_string(),
+        title: stem.to_string(),
+        source: Source::Direct,
+    }
+}
+
+fn is_media_url(input: &str) -> bool {
+    if !(input.starts_with("http://") || input.starts_with("https://")) {
+        return false;
+    }
+    let path = input.split(['?', '#']).next().unwrap_or(input);
+    path.rsplit_once('.')
+        .is_some_and(|(_, ext)| MEDIA_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Decodes `%XX` escapes in a `file://` URL path.
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Expands a URL into the videos it refers to, expanding playlists and
+/// channels through `yt-dlp --flat-playlist -J`.
+async fn expand(url: &str) -> Result<Vec<Item>> {
+    let output = Command::new("yt-dlp")
+        .args(["--flat-playlist", "-J", url])
+        .output()
+        .await
+        .context("Failed to execute yt-dlp")?;
+
+    if !output.status.success() {
+        return Err(ProcessError::new("yt-dlp", &output.stderr).into());
+    }
+
+    let info: Value =
+        serde_json::from_slice(&output.stdout).context("Failed to parse yt-dlp info JSON")?;
+
+    if info["_type"] != "playlist" {
+        return Ok(vec![item_from_info(&info, url)]);
+    }
+
+    let entries = info["entries"]
+        .as_array()
+        .context("Playlist has no entries")?;
+
+    let mut items = Vec::new();
+    for entry in entries {
+        let id = entry["id"].as_str().unwrap_or_default();
+        let fallback = format!("https://www.youtube.com/watch?v={}", id);
+        let entry_url = entry["url"].as_str().unwrap_or(&fallback);
+        // Channels list their tabs as nested playlists.
+        if entry["_type"] == "playlist" || entry["ie_key"] == "YoutubeTab" {
+            let nested = Box::pin(expand(entry_url)).await?;
+            items.extend(nested);
+        } else {
+            items.push(item_from_info(entry, entry_url));
+        }
+    }
+
+    Ok(items)
+}
+
+fn item_from_info(info: &Value, url: &str) -> Item {
+    let id = info["id"].as_str().unwrap_or("unknown").to_string();
+    Item {
+        url: info["webpage_url"].as_str().unwrap_or(url).to_string(),
+        title: info["title"].as_str().unwrap_or(&id).to_string(),
+        id,
+        source: Source::YtDlp,
+    }
+}
+
+/// Fills in an output file name template. Supported placeholders are
+/// `{id}`, `{title}`, `{index}` (position in the run, starting at 1) and
+/// `{ext}`.
+pub fn output_path(template: &str, item: &Item, index: usize, format: Format) -> String {
+    template
+        .replace("{id}", &sanitize(&item.id))
+        .replace("{title}", &sanitize(&item.title))
+        .replace("{index}", &format!("{:03}", index))
+        .replace("{ext}", format.extension())
+}
+
+/// Path for one chapter of `output_file` with `--chapter-files`, e.g.
+/// `talk.02 Setup.txt` for chapter 2 of `talk.txt`.
+pub fn chapter_path(output_file: &str, number: usize, title: &str) -> String {
+    let path = Path::new(output_file);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}.{:02} {}", stem, number, sanitize(title));
+    if let Some(ext) = path.extension() {
+        name = format!("{}.{}", name, ext.to_string_lossy());
+    }
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Replaces characters that are not allowed in file names.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("/tmp/my%20talk.mp3"), "/tmp/my talk.mp3");
+        assert_eq!(percent_decode("/tmp/caf%C3%A9.wav"), "/tmp/café.wav");
+        assert_eq!(percent_decode("/tmp/%2f%2F"), "/tmp///");
+    }
+
+    #[test]
+    fn keeps_invalid_escapes() {
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("50%zz off"), "50%zz off");
+        assert_eq!(percent_decode("%+1"), "%+1");
+        assert_eq!(percent_decode("%4"), "%4");
+    }
+
+    #[test]
+    fn recognizes_direct_media_links() {
+        assert!(is_media_url("https://example.com/talk.mp3"));
+        assert!(is_media_url(
+            "http://example.com/a/b/Talk.WEBM?token=abc#t=10"
+        ));
+        assert!(!is_media_url("https://www.youtube.com/watch?v=abc.mp4"));
+        assert!(!is_media_url("https://example.com/talk.mp3.html"));
+        assert!(!is_media_url("https://example.com"));
+        assert!(!is_media_url("/tmp/talk.mp3"));
+    }
+
+    #[test]
+    fn direct_items_are_named_after_the_file() {
+        let item = direct_item("https://example.com/media/talk.final.mp3?dl=1");
+        assert_eq!(item.id, "talk.final");
+        assert_eq!(item.source, Source::Direct);
+        assert_eq!(direct_item("talk.wav").title, "talk");
+    }
+}
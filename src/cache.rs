@@ -0,0 +1,242 @@
+use anyhow::Context;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::io::AsyncReadExt;
+
+use crate::error::Result;
+use crate::transcript::Transcript;
+
+/// On-disk transcript cache under the user's cache directory
+/// (`$XDG_CACHE_HOME/yt-dlts` on Linux).
+///
+/// Entries are looked up by video ID first, so a cache hit skips the
+/// download entirely, and by a hash of the converted audio second, which
+/// also covers local files and re-uploads of the same video.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+/// A cached transcript as listed by [`Cache::list`].
+pub struct Entry {
+    pub key: String,
+    pub title: String,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    path: PathBuf,
+}
+
+impl Entry {
+    /// How long ago the entry was written.
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(now().saturating_sub(self.created))
+    }
+}
+
+impl Cache {
+    pub fn open() -> Result<Self> {
+        let dir = dirs::cache_dir()
+            .context("Could not determine the cache directory")?
+            .join("yt-dlts");
+        Cache::at(dir)
+    }
+
+    /// Opens a cache kept in `dir` rather than the user's cache directory.
+    pub fn at(dir: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&dir).context("Failed to create cache directory")?;
+        Ok(Cache { dir })
+    }
+
+    /// Cache key for a video ID, including everything that changes the
+    /// transcript (backend, model and request options).
+    pub fn video_key(id: &str, options: &str) -> String {
+        format!("video:{}|{}", id, options)
+    }
+
+    /// Cache key for a hash of the converted audio.
+    pub fn audio_key(hash: &str, options: &str) -> String {
+        format!("audio:{}|{}", hash, options)
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Transcript> {
+        let contents = tokio::fs::read(self.path(key)).await.ok()?;
+        let entry: Value = serde_json::from_slice(&contents).ok()?;
+        // Guard against hash collisions in the file name
+        if entry["key"] != key {
+            return None;
+        }
+        serde_json::from_value(entry["transcript"].clone()).ok()
+    }
+
+    pub async fn put(&self, key: &str, title: &str, transcript: &Transcript) -> Result<()> {
+        let entry = json!({
+            "key": key,
+            "title": title,
+            "created": now(),
+            "transcript": transcript,
+        });
+
+        // Write to a temporary file first so a crash never leaves a
+        // truncated entry behind
+        let path = self.path(key);
+        let tmp = path.with_extension("tmp");
+        let contents = serde_json::to_vec(&entry).context("Failed to serialize cache entry")?;
+        tokio::fs::write(&tmp, contents)
+            .await
+            .context("Failed to write cache entry")?;
+        tokio::fs::rename(&tmp, &path)
+            .await
+            .context("Failed to write cache entry")?;
+        Ok(())
+    }
+
+    pub async fn list(&self) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        let mut dir = tokio::fs::read_dir(&self.dir)
+            .await
+            .context("Failed to read cache directory")?;
+        while let Some(file) = dir
+            .next_entry()
+            .await
+            .context("Failed to read cache directory")?
+        {
+            let path = file.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Ok(contents) = tokio::fs::read(&path).await else {
+                continue;
+            };
+            let Ok(entry) = serde_json::from_slice::<Value>(&contents) else {
+                continue;
+            };
+            entries.push(Entry {
+                key: entry["key"].as_str().unwrap_or_default().to_string(),
+                title: entry["title"].as_str().unwrap_or_default().to_string(),
+                created: entry["created"].as_u64().unwrap_or_default(),
+                path,
+            });
+        }
+        entries.sort_by_key(|entry| entry.created);
+        Ok(entries)
+    }
+
+    /// Removes entries older than `max_age` and returns how many were removed.
+    pub async fn prune(&self, max_age: Duration) -> Result<usize> {
+        let cutoff = now().saturating_sub(max_age.as_secs());
+        let mut removed = 0;
+        for entry in self.list().await? {
+            if entry.created < cutoff {
+                tokio::fs::remove_file(&entry.path)
+                    .await
+                    .context("Failed to remove cache entry")?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}.json", hex(&Sha256::digest(key.as_bytes()))))
+    }
+}
+
+/// SHA-256 of a file's contents, as lowercase hex.
+pub async fn hash_file(path: &str) -> Result<String> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .context("Failed to open audio file for hashing")?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .await
+            .context("Failed to read audio file for hashing")?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcript(text: &str) -> Transcript {
+        Transcript {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn entries_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::at(dir.path().to_path_buf()).unwrap();
+        let key = Cache::video_key("dQw4w9WgXcQ", "groq|whisper-large-v3");
+
+        assert!(cache.get(&key).await.is_none());
+        cache.put(&key, "Talk", &transcript("Hello")).await.unwrap();
+        assert_eq!(cache.get(&key).await.unwrap().text, "Hello");
+        assert!(cache
+            .get(&Cache::video_key("dQw4w9WgXcQ", "openai|whisper-1"))
+            .await
+            .is_none());
+
+        let entries = cache.list().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key, key);
+        assert_eq!(entries[0].title, "Talk");
+        assert!(entries[0].age() < Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn prune_removes_old_entries_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::at(dir.path().to_path_buf()).unwrap();
+        let old = Cache::audio_key("0123abcd", "groq|whisper-large-v3");
+        let new = Cache::audio_key("4567ef01", "groq|whisper-large-v3");
+        cache.put(&old, "Old", &transcript("Old")).await.unwrap();
+        cache.put(&new, "New", &transcript("New")).await.unwrap();
+
+        // Backdate one entry by a day
+        let path = cache.path(&old);
+        let mut entry: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        entry["created"] = json!(now() - 86400);
+        std::fs::write(&path, serde_json::to_vec(&entry).unwrap()).unwrap();
+
+        assert_eq!(cache.prune(Duration::from_secs(3600)).await.unwrap(), 1);
+        assert!(cache.get(&old).await.is_none());
+        assert_eq!(cache.get(&new).await.unwrap().text, "New");
+        assert_eq!(cache.prune(Duration::from_secs(3600)).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn files_hash_to_sha256() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audio.opus");
+        std::fs::write(&path, "abc").unwrap();
+        assert_eq!(
+            hash_file(path.to_str().unwrap()).await.unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use anyhow::Context;
+use serde_json::Value;
+use std::path::Path;
+use tokio::process::Command;
+
+use crate::error::{ProcessError, Result};
+use crate::progress::Tracker;
+use crate::transcript::{Chapter, Metadata, Segment, Transcript};
+
+/// Which of a video's own captions can stand in for a transcript.
+#[derive(Clone, Debug)]
+pub struct Captions {
+    /// Language code; `en` also matches regional variants such as `en-US`.
+    pub lang: String,
+    /// Also accept auto-generated captions.
+    pub allow_auto: bool,
+}
+
+/// Looks for a subtitle track in the requested language and, if there is
+/// one, downloads it into `workdir` and returns it as a transcript.
+///
+/// Human-made tracks are always preferred; auto-generated ones are only
+/// used when `allow_auto` is set.
+pub async fn fetch(
+    captions: &Captions,
+    url: &str,
+    workdir: &Path,
+    index: usize,
+    tracker: &Tracker,
+) -> Result<Option<Transcript>> {
+    let output = Command::new("yt-dlp")
+        .args(["-J", "--skip-download", url])
+        .output()
+        .await
+        .context("Failed to execute yt-dlp")?;
+
+    if !output.status.success() {
+        return Err(ProcessError::new("yt-dlp", &output.stderr).into());
+    }
+
+    let info: Value =
+        serde_json::from_slice(&output.stdout).context("Failed to parse yt-dlp info JSON")?;
+
+    let (lang, auto) = match find_track(&info["subtitles"], &captions.lang) {
+        Some(lang) => (lang, false),
+        None if captions.allow_auto => {
+            match find_track(&info["automatic_captions"], &captions.lang) {
+                Some(lang) => (lang, true),
+                None => return Ok(None),
+            }
+        }
+        None => return Ok(None),
+    };
+
+    let prefix = workdir.join(format!("captions.{}", index));
+    let prefix = prefix
+        .to_str()
+        .context("Temporary directory path is not valid UTF-8")?;
+    let output = Command::new("yt-dlp")
+        .args([
+            "--skip-download",
+            if auto {
+                "--write-auto-subs"
+            } else {
+                "--write-subs"
+            },
+            "--sub-langs",
+            &lang,
+            "--sub-format",
+            "vtt",
+            "-o",
+            prefix,
+            url,
+        ])
+        .output()
+        .await
+        .context("Failed to execute yt-dlp")?;
+
+    if !output.status.success() {
+        return Err(ProcessError::new("yt-dlp", &output.stderr).into());
+    }
+
+    let vtt = tokio::fs::read_to_string(format!("{}.{}.vtt", prefix, lang))
+        .await
+        .context("Failed to read downloaded captions")?;
+
+    tracker.message(&format!(
+        "Using {} captions ({}) for {}",
+        if auto { "auto-generated" } else { "manual" },
+        lang,
+        info["title"].as_str().unwrap_or(url)
+    ));
+    let mut transcript = parse_vtt(&vtt, auto);
+    transcript.chapters = Chapter::list(&info);
+    transcript.source = Some(Metadata::from_info(
+        &info,
+        "captions",
+        if auto { "auto-generated" } else { "manual" },
+    ));
+    Ok(Some(transcript))
+}
+
+/// Picks the track key matching `lang`, either exactly or as a regional
+/// variant (`en` matches `en-US`).
+fn find_track(tracks: &Value, lang: &str) -> Option<String> {
+    let tracks = tracks.as_object()?;
+    if tracks.contains_key(lang) {
+        return Some(lang.to_string());
+    }
+    let prefix = format!("{}-", lang);
+    tracks.keys().find(|key| key.starts_with(&prefix)).cloned()
+}
+
+/// Parses a WebVTT file into transcript segments.
+///
+/// YouTube's auto-generated captions repeat the previous line at the start
+/// of every cue and carry inline word timings; both are stripped here. The
+/// repeats are only dropped when `auto` is set, since consecutive manual
+/// cues may legitimately say the same thing.
+pub fn parse_vtt(vtt: &str, auto: bool) -> Transcript {
+    let mut segments = Vec::new();
+    let mut last_line = String::new();
+
+    for block in vtt.replace("\r\n", "\n").split("\n\n") {
+        let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+        let Some(timing) = lines.next() else {
+            continue;
+        };
+        let Some((start, end)) = parse_timing(timing) else {
+            continue;
+        };
+
+        let mut text = Vec::new();
+        for line in lines {
+            let line = strip_tags(line);
+            let line = line.trim();
+            if line.is_empty() || (auto && line == last_line) {
+                continue;
+            }
+            last_line = line.to_string();
+            text.push(last_line.clone());
+        }
+        if !text.is_empty() {
+            segments.push(Segment {
+                start,
+                end,
+                text: text.join(" "),
+                ..Default::default()
+            });
+        }
+    }
+
+    let text = segments
+        .iter()
+        .map(|s| s.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Transcript {
+        text,
+        segments,
+        ..Default::default()
+    }
+}
+
+fn parse_timing(line: &str) -> Option<(f64, f64)> {
+    let (start, rest) = line.split_once("-->")?;
+    let end = rest.split_whitespace().next()?;
+    Some((parse_timestamp(start.trim())?, parse_timestamp(end)?))
+}
+
+/// Parses `HH:MM:SS.mmm` or `MM:SS.mmm`.
+fn parse_timestamp(value: &str) -> Option<f64> {
+    value.split(':').try_fold(0.0, |total, part| {
+        Some(total * 60.0 + part.parse::<f64>().ok()?)
+    })
+}
+
+fn strip_tags(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut in_tag = false;
+    for c in line.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUTO: &str = "WEBVTT\nKind: captions\nLanguage: en\n\n\
+00:00:00.000 --> 00:00:02.500 align:start position:0%\n\
+hello<00:00:00.800><c> everyone</c>\n\n\
+00:00:02.500 --> 00:00:05.000 align:start position:0%\n\
+hello everyone\n\
+welcome<00:00:03.100><c> to</c><00:00:03.400><c> the</c><00:00:03.600><c> show</c>\n";
+
+    const MANUAL: &str = "WEBVTT\r\n\r\n1\r\n\
+00:01.000 --> 00:02.000\r\nNo.\r\n\r\n2\r\n\
+00:02.000 --> 00:03.000\r\nNo.\r\n\r\n3\r\n\
+01:00:03.000 --> 01:00:04.250\r\nTom &amp; Jerry\r\n<i>together</i>\r\n";
+
+    #[test]
+    fn auto_captions_drop_repeated_lines_and_word_timings() {
+        let transcript = parse_vtt(AUTO, true);
+        let texts: Vec<_> = transcript
+            .segments
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect();
+        assert_eq!(texts, ["hello everyone", "welcome to the show"]);
+        assert_eq!(transcript.text, "hello everyone welcome to the show");
+        assert_eq!(transcript.segments[1].start, 2.5);
+    }
+
+    #[test]
+    fn manual_captions_keep_repeated_cues() {
+        let transcript = parse_vtt(MANUAL, false);
+        let texts: Vec<_> = transcript
+            .segments
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect();
+        assert_eq!(texts, ["No.", "No.", "Tom & Jerry together"]);
+        assert_eq!(transcript.segments[0].start, 1.0);
+        assert_eq!(transcript.segments[2].start, 3603.0);
+        assert_eq!(transcript.segments[2].end, 3604.25);
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("00:00.500"), Some(0.5));
+        assert_eq!(parse_timestamp("01:02:03.004"), Some(3723.004));
+        assert_eq!(parse_timestamp("1:xx.000"), None);
+        assert_eq!(
+            parse_timing("00:01.000 --> 00:02.000 line:90%"),
+            Some((1.0, 2.0))
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use anyhow::{Context, Result};
+use tokio::process::Command;
+
+use crate::error::ProcessError;
+
+/// Largest file we send in a single request (Groq rejects uploads above 25 MB).
+const MAX_UPLOAD_BYTES: u64 = 25 * 1024 * 1024;
+
+/// Seconds of audio shared between neighbouring chunks.
+const CHUNK_OVERLAP_SECS: f64 = 5.0;
+
+/// How far back from the ideal cut point we look for a silence to cut at.
+const SILENCE_SEARCH_SECS: f64 = 30.0;
+
+/// Number of words at each chunk edge compared when removing the overlap,
+/// enough for the overlap even at a brisk four words a second.
+const MERGE_WINDOW_WORDS: usize = (CHUNK_OVERLAP_SECS * 4.0) as usize;
+
+/// Shortest run of shared words accepted as the overlap.
+const MERGE_MIN_WORDS: usize = 3;
+
+/// How many words the overlap may be away from the chunk edges, since words
+/// cut in half at a chunk edge are often dropped or misheard.
+const MERGE_EDGE_WORDS: usize = 3;
+
+/// A piece of the converted audio, written next to the original file.
+pub struct Chunk {
+    pub path: String,
+    pub start: f64,
+}
+
+/// Splits `input_file` into overlapping chunks small enough to upload.
+///
+/// Returns an empty list when the file already fits in a single request.
+pub async fn split_audio(input_file: &str) -> Result<Vec<Chunk>> {
+    let size = tokio::fs::metadata(input_file)
+        .await
+        .context("Failed to read audio file metadata")?
+        .len();
+    if size <= MAX_UPLOAD_BYTES {
+        return Ok(Vec::new());
+    }
+
+    let duration = probe_duration(input_file).await?;
+    let bytes_per_sec = size as f64 / duration;
+    // Leave some headroom for container overhead and bitrate spikes.
+    let max_len = MAX_UPLOAD_BYTES as f64 * 0.9 / bytes_per_sec - CHUNK_OVERLAP_SECS;
+    if max_len <= CHUNK_OVERLAP_SECS {
+        anyhow::bail!("Audio bitrate is too high to split into uploadable chunks");
+    }
+
+    let silences = detect_silences(input_file).await?;
+
+    let mut chunks = Vec::new();
+    let mut start = 0.0;
+    while start < duration {
+        let ideal_cut = start + max_len;
+        let cut = if ideal_cut >= duration {
+            duration
+        } else {
+            pick_cut(&silences, ideal_cut, start + CHUNK_OVERLAP_SECS * 2.0)
+        };
+        let end = (cut + CHUNK_OVERLAP_SECS).min(duration);
+
+        let path = chunk_path(input_file, chunks.len());
+        extract_chunk(input_file, &path, start, end - start).await?;
+        chunks.push(Chunk { path, start });
+
+        start = cut;
+    }
+
+    Ok(chunks)
+}
+
+/// Removes the chunk files created by [`split_audio`].
+pub async fn remove_chunks(chunks: &[Chunk]) -> Result<()> {
+    for chunk in chunks {
+        tokio::fs::remove_file(&chunk.path)
+            .await
+            .context("Failed to remove audio chunk")?;
+    }
+    Ok(())
+}
+
+/// Appends `next` to `prev`, dropping the words both transcripts share
+/// because of the chunk overlap.
+pub fn merge_overlap(prev: &str, next: &str) -> String {
+    let prev_words: Vec<&str> = prev.split_whitespace().collect();
+    let next_words: Vec<&str> = next.split_whitespace().collect();
+    if prev_words.is_empty() {
+        return next_words.join(" ");
+    }
+    if next_words.is_empty() {
+        return prev_words.join(" ");
+    }
+
+    let tail_start = prev_words.len().saturating_sub(MERGE_WINDOW_WORDS);
+    let tail: Vec<String> = prev_words[tail_start..]
+        .iter()
+        .map(|w| normalize(w))
+        .collect();
+    let head: Vec<String> = next_words
+        .iter()
+        .take(MERGE_WINDOW_WORDS)
+        .map(|w| normalize(w))
+        .collect();
+
+    // Longest run of words present in both the tail of `prev` and the head of
+    // `next`. Whisper rarely transcribes the overlap identically, so the run
+    // only has to end close to the end of `prev` and start close to the start
+    // of `next`, rather than being an exact suffix and prefix.
+    let mut best = (0, 0, 0);
+    let mut lengths = vec![vec![0usize; head.len() + 1]; tail.len() + 1];
+    for i in 1..=tail.len() {
+        for j in 1..=head.len() {
+            if !tail[i - 1].is_empty() && tail[i - 1] == head[j - 1] {
+                lengths[i][j] = lengths[i - 1][j - 1] + 1;
+                let len = lengths[i][j];
+                let at_edges = i + MERGE_EDGE_WORDS >= tail.len() && j - len <= MERGE_EDGE_WORDS;
+                if at_edges && len > best.0 {
+                    best = (len, i - len, j - len);
+                }
+            }
+        }
+    }
+
+    let (len, tail_pos, head_pos) = best;
+    if len < MERGE_MIN_WORDS {
+        return format!("{} {}", prev_words.join(" "), next_words.join(" "));
+    }
+    let mut merged = prev_words[..tail_start + tail_pos].to_vec();
+    merged.extend_from_slice(&next_words[head_pos..]);
+    merged.join(" ")
+}
+
+fn normalize(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Picks the last silence before `ideal_cut`, falling back to `ideal_cut`
+/// itself when there is no silence in the search window.
+fn pick_cut(silences: &[(f64, f64)], ideal_cut: f64, min_cut: f64) -> f64 {
+    let window_start = (ideal_cut - SILENCE_SEARCH_SECS).max(min_cut);
+    silences
+        .iter()
+        .rev()
+        .map(|(start, end)| (start + end) / 2.0)
+        .find(|mid| *mid >= window_start && *mid <= ideal_cut)
+        .unwrap_or(ideal_cut)
+}
+
+fn chunk_path(input_file: &str, index: usize) -> String {
+    match input_file.rsplit_once('.') {
+        Some((stem, ext)) => format!("{}.chunk{}.{}", stem, index, ext),
+        None => format!("{}.chunk{}", input_file, index),
+    }
+}
+
+async fn probe_duration(input_file: &str) -> Result<f64> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+            input_file,
+        ])
+        .output()
+        .await
+        .context("Failed to execute ffprobe")?;
+
+    if !output.status.success() {
+        return Err(ProcessError::new("ffprobe", &output.stderr).into());
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .context("Failed to parse audio duration")
+}
+
+/// Runs ffmpeg's `silencedetect` filter and returns `(start, end)` pairs.
+async fn detect_silences(input_file: &str) -> Result<Vec<(f64, f64)>> {
+    let output = Command::new("ffmpeg")
+        .args([
+            "-i",
+            input_file,
+            "-af",
+            "silencedetect=noise=-30dB:d=0.5",
+            "-f",
+            "null",
+            "-",
+        ])
+        .output()
+        .await
+        .context("Failed to execute ffmpeg")?;
+
+    if !output.status.success() {
+        return Err(ProcessError::new("ffmpeg", &output.stderr).into());
+    }
+
+    let mut silences = Vec::new();
+    let mut current_start = None;
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        if let Some(value) = field_after(line, "silence_start:") {
+            current_start = Some(value);
+        } else if let Some(end) = field_after(line, "silence_end:") {
+            if let Some(start) = current_start.take() {
+                silences.push((start, end));
+            }
+        }
+    }
+
+    Ok(silences)
+}
+
+fn field_after(line: &str, key: &str) -> Option<f64> {
+    let (_, rest) = line.split_once(key)?;
+    rest.split_whitespace().next()?.parse().ok()
+}
+
+async fn extract_chunk(input_file: &str, output_file: &str, start: f64, length: f64) -> Result<()> {
+    let output = Command::new("ffmpeg")
+        .args([
+            "-y",
+            "-ss",
+            &format!("{:.3}", start),
+            "-i",
+            input_file,
+            "-t",
+            &format!("{:.3}", length),
+            "-c",
+            "copy",
+            output_file,
+        ])
+        .output()
+        .await
+        .context("Failed to execute ffmpeg")?;
+
+    if !output.status.success() {
+        return Err(ProcessError::new("ffmpeg", &output.stderr).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_drops_the_shared_overlap() {
+        let prev = "we looked at the budget and then we moved on to hiring plans";
+        let next = "moved on to hiring plans for next year, starting in March";
+        assert_eq!(
+            merge_overlap(prev, next),
+            "we looked at the budget and then we moved on to hiring plans for next year, starting in March"
+        );
+    }
+
+    #[test]
+    fn merge_tolerates_misheard_edge_words() {
+        let prev = "the results were better than expected, so we shipped it ear-";
+        let next = "uh expected, so we shipped it early and celebrated";
+        assert_eq!(
+            merge_overlap(prev, next),
+            "the results were better than expected, so we shipped it early and celebrated"
+        );
+    }
+
+    #[test]
+    fn merge_keeps_matches_away_from_the_edges() {
+        let prev = "we discussed the budget of the project and then we moved on to hiring plans for next year";
+        let next = "completely new topic now, the project timeline is tight";
+        assert_eq!(merge_overlap(prev, next), format!("{} {}", prev, next));
+    }
+
+    #[test]
+    fn merge_needs_more_than_a_couple_of_words() {
+        assert_eq!(
+            merge_overlap("and so on and", "and so we left"),
+            "and so on and and so we left"
+        );
+    }
+
+    #[test]
+    fn merge_handles_empty_parts() {
+        assert_eq!(merge_overlap("", " hello  world "), "hello world");
+        assert_eq!(merge_overlap("hello world", ""), "hello world");
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::Context;
+use serde_json::Value;
+use tokio::process::Command;
+
+use crate::batch::{Item, Source};
+use crate::error::{ProcessError, Result};
+use crate::transcript::Chapter;
+
+/// The part of each video to transcribe.
+#[derive(Clone, Debug)]
+pub enum Clip {
+    /// The same time range in every video.
+    Range(Range),
+    /// A chapter by title or number (starting at 1), looked up per video.
+    Chapter(String),
+}
+
+impl Clip {
+    /// Identifies a clip, or the whole video for `None`, in cache keys.
+    pub fn cache_key(clip: Option<&Clip>) -> String {
+        let (start, end, chapter) = match clip {
+            Some(Clip::Range(range)) => (
+                range.start.to_string(),
+                range.end.map(|end| end.to_string()).unwrap_or_default(),
+                "",
+            ),
+            Some(Clip::Chapter(chapter)) => (String::new(), String::new(), chapter.as_str()),
+            None => (String::new(), String::new(), ""),
+        };
+        format!("start={}|end={}|chapter={}", start, end, chapter)
+    }
+
+    /// Works out which part of `item` to transcribe.
+    pub async fn resolve(&self, item: &Item) -> Result<Range> {
+        match self {
+            Clip::Range(range) => Ok(*range),
+            Clip::Chapter(_) if item.source != Source::YtDlp => {
+                Err(anyhow::anyhow!("Chapters only work with videos yt-dlp can download").into())
+            }
+            Clip::Chapter(chapter) => Ok(find_chapter(&item.url, chapter).await?),
+        }
+    }
+}
+
+/// A time range in seconds from the start of the video. A missing end means
+/// the end of the video.
+#[derive(Clone, Copy, Debug)]
+pub struct Range {
+    pub start: f64,
+    pub end: Option<f64>,
+}
+
+impl Range {
+    /// Value for yt-dlp's `--download-sections`.
+    pub fn download_section(&self) -> String {
+        match self.end {
+            Some(end) => format!("*{}-{}", self.start, end),
+            None => format!("*{}-inf", self.start),
+        }
+    }
+
+    /// ffmpeg options cutting out this range. They work both as input
+    /// options and, for unseekable input, as output options.
+    pub fn ffmpeg_args(&self) -> Vec<String> {
+        let mut args = vec!["-ss".to_string(), self.start.to_string()];
+        if let Some(end) = self.end {
+            args.extend(["-t".to_string(), (end - self.start).to_string()]);
+        }
+        args
+    }
+
+    /// Length of the range, if it has an end.
+    pub fn duration(&self) -> Option<f64> {
+        self.end.map(|end| end - self.start)
+    }
+}
+
+/// Looks up a chapter by number or title in yt-dlp's info JSON. Titles are
+/// matched case-insensitively, exactly first and then as a substring.
+async fn find_chapter(url: &str, chapter: &str) -> anyhow::Result<Range> {
+    let output = Command::new("yt-dlp")
+        .args(["-J", "--skip-download", url])
+        .output()
+        .await
+        .context("Failed to execute yt-dlp")?;
+
+    if !output.status.success() {
+        return Err(ProcessError::new("yt-dlp", &output.stderr).into());
+    }
+
+    let info: Value =
+        serde_json::from_slice(&output.stdout).context("Failed to parse yt-dlp info JSON")?;
+    let chapters = Chapter::list(&info);
+    if chapters.is_empty() {
+        anyhow::bail!("The video has no chapters");
+    }
+
+    let wanted = chapter.to_lowercase();
+    let found = match chapter.parse::<usize>() {
+        Ok(number) => number.checked_sub(1).and_then(|i| chapters.get(i)),
+        Err(_) => chapters
+            .iter()
+            .find(|c| c.title.to_lowercase() == wanted)
+            .or_else(|| {
+                chapters
+                    .iter()
+                    .find(|c| c.title.to_lowercase().contains(&wanted))
+            }),
+    }
+    .with_context(|| format!("No chapter matching {:?}", chapter))?;
+
+    Ok(Range {
+        start: found.start,
+        end: Some(found.end),
+    })
+}
+
+/// Parses `HH:MM:SS`, `MM:SS` or plain seconds, with optional fractions.
+pub fn parse_time(value: &str) -> Option<f64> {
+    let value = value.trim();
+    if value.is_empty() || value.split(':').count() > 3 {
+        return None;
+    }
+    value.split(':').try_fold(0.0, |total, part| {
+        // Rules out signs, exponents, `inf` and `NaN`, which f64 accepts
+        if !part.chars().all(|c| c.is_ascii_digit() || c == '.') {
+            return None;
+        }
+        Some(total * 60.0 + part.parse::<f64>().ok()?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_time("90"), Some(90.0));
+        assert_eq!(parse_time("1.5"), Some(1.5));
+        assert_eq!(parse_time("12:00"), Some(720.0));
+        assert_eq!(parse_time("1:02:03.5"), Some(3723.5));
+        assert_eq!(parse_time(" 00:40:00 "), Some(2400.0));
+    }
+
+    #[test]
+    fn rejects_invalid_times() {
+        for value in [
+            "", "1:2:3:4", "-5", "+5", "1e3", "inf", "NaN", "12:", "1..5", "ten",
+        ] {
+            assert_eq!(parse_time(value), None, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn clip_cache_keys() {
+        assert_eq!(Clip::cache_key(None), "start=|end=|chapter=");
+        let range = Clip::Range(Range {
+            start: 90.5,
+            end: None,
+        });
+        assert_eq!(Clip::cache_key(Some(&range)), "start=90.5|end=|chapter=");
+        let chapter = Clip::Chapter("Intro".to_string());
+        assert_eq!(Clip::cache_key(Some(&chapter)), "start=|end=|chapter=Intro");
+    }
+}
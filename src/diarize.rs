@@ -0,0 +1,235 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::error::Result;
+use crate::transcript::Transcript;
+
+/// A stretch of audio in which one speaker talks, in seconds from the start
+/// of the audio.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Turn {
+    pub speaker: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Something that can tell who speaks when in an audio file.
+#[async_trait]
+pub trait Diarizer: Send + Sync {
+    /// Identifies the service and its settings in cache keys.
+    fn cache_key(&self) -> String;
+
+    async fn diarize(&self, file_path: &str) -> Result<Vec<Turn>>;
+}
+
+/// A diarization server, e.g. a small wrapper around pyannote, that accepts
+/// the audio as a multipart `file` upload and answers with
+/// `{"segments": [{"speaker", "start", "end"}, ...]}` or the bare array.
+pub struct HttpDiarizer {
+    url: String,
+    num_speakers: Option<u32>,
+    client: reqwest::Client,
+}
+
+impl HttpDiarizer {
+    pub fn new(url: String, num_speakers: Option<u32>) -> Self {
+        HttpDiarizer {
+            url,
+            num_speakers,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Diarizer for HttpDiarizer {
+    fn cache_key(&self) -> String {
+        format!(
+            "diarize={}|speakers={}",
+            self.url,
+            self.num_speakers.map(|n| n.to_string()).unwrap_or_default()
+        )
+    }
+
+    async fn diarize(&self, file_path: &str) -> Result<Vec<Turn>> {
+        let file_bytes = tokio::fs::read(file_path)
+            .await
+            .context("Failed to read audio file")?;
+        let mut form = Form::new().part(
+            "file",
+            Part::bytes(file_bytes).file_name(file_path.to_string()),
+        );
+        if let Some(num_speakers) = self.num_speakers {
+            form = form.text("num_speakers", num_speakers.to_string());
+        }
+
+        let response = self
+            .client
+            .post(&self.url)
+            .multipart(form)
+            .send()
+            .await
+            .context("Failed to reach the diarization service")?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Diarization failed ({}): {}", status, body.trim()).into());
+        }
+
+        let json: Value = response
+            .json()
+            .await
+            .context("Failed to parse diarization response")?;
+        let turns = match json.get("segments") {
+            Some(segments) => segments.clone(),
+            None => json,
+        };
+        Ok(serde_json::from_value(turns).context("Failed to extract speaker turns from JSON")?)
+    }
+}
+
+/// Labels every segment with the speaker whose turns overlap it the most.
+/// Speakers are renamed to `Speaker 1`, `Speaker 2`, ... in order of
+/// appearance.
+pub fn assign_speakers(transcript: &mut Transcript, turns: &[Turn]) {
+    let mut names: HashMap<&str, String> = HashMap::new();
+    for segment in &mut transcript.segments {
+        let mut overlaps: HashMap<&str, f64> = HashMap::new();
+        for turn in turns {
+            let overlap = segment.end.min(turn.end) - segment.start.max(turn.start);
+            if overlap > 0.0 {
+                *overlaps.entry(turn.speaker.as_str()).or_default() += overlap;
+            }
+        }
+        let Some((speaker, _)) = overlaps
+            .into_iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+        else {
+            continue;
+        };
+
+        let next = names.len() + 1;
+        let name = names
+            .entry(speaker)
+            .or_insert_with(|| format!("Speaker {}", next));
+        segment.speaker = Some(name.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::Segment;
+
+    /// Answers with fixed turns instead of asking a service.
+    struct StubDiarizer(Vec<Turn>);
+
+    #[async_trait]
+    impl Diarizer for StubDiarizer {
+        fn cache_key(&self) -> String {
+            "diarize=stub".to_string()
+        }
+
+        async fn diarize(&self, _file_path: &str) -> Result<Vec<Turn>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn turn(speaker: &str, start: f64, end: f64) -> Turn {
+        Turn {
+            speaker: speaker.to_string(),
+            start,
+            end,
+        }
+    }
+
+    fn transcript(times: &[(f64, f64)]) -> Transcript {
+        Transcript {
+            segments: times
+                .iter()
+                .map(|&(start, end)| Segment {
+                    start,
+                    end,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    async fn labels(times: &[(f64, f64)], turns: Vec<Turn>) -> Vec<Option<String>> {
+        let mut transcript = transcript(times);
+        let turns = StubDiarizer(turns).diarize("audio.wav").await.unwrap();
+        assign_speakers(&mut transcript, &turns);
+        transcript.segments.into_iter().map(|s| s.speaker).collect()
+    }
+
+    fn speakers(names: &[Option<&str>]) -> Vec<Option<String>> {
+        names.iter().map(|name| name.map(str::to_string)).collect()
+    }
+
+    #[tokio::test]
+    async fn speakers_are_numbered_by_first_appearance() {
+        let turns = vec![
+            turn("SPEAKER_01", 0.0, 5.0),
+            turn("SPEAKER_00", 5.0, 9.0),
+            turn("SPEAKER_01", 9.0, 12.0),
+        ];
+        assert_eq!(
+            labels(&[(0.5, 4.0), (5.5, 8.0), (9.5, 11.0)], turns).await,
+            speakers(&[Some("Speaker 1"), Some("Speaker 2"), Some("Speaker 1")])
+        );
+    }
+
+    #[tokio::test]
+    async fn segments_take_the_speaker_with_the_most_overlap() {
+        let turns = vec![
+            turn("A", 0.0, 2.0),
+            turn("B", 2.0, 3.0),
+            turn("A", 3.0, 3.5),
+            turn("B", 3.5, 6.0),
+        ];
+        // A overlaps 2 s of the second segment and B 2.5 s, both across
+        // two turns
+        assert_eq!(
+            labels(&[(0.0, 1.0), (0.5, 5.0)], turns).await,
+            speakers(&[Some("Speaker 1"), Some("Speaker 2")])
+        );
+    }
+
+    #[tokio::test]
+    async fn segments_without_overlapping_turns_stay_unlabelled() {
+        let turns = vec![turn("A", 0.0, 2.0), turn("B", 10.0, 12.0)];
+        assert_eq!(
+            labels(&[(0.0, 1.0), (2.0, 10.0), (11.0, 12.0)], turns).await,
+            speakers(&[Some("Speaker 1"), None, Some("Speaker 2")])
+        );
+    }
+
+    #[tokio::test]
+    async fn ties_go_to_the_lowest_speaker_id() {
+        let turns = vec![turn("B", 0.0, 1.0), turn("A", 1.0, 2.0)];
+        assert_eq!(
+            labels(&[(0.0, 2.0), (1.0, 2.0)], turns).await,
+            speakers(&[Some("Speaker 1"), Some("Speaker 1")])
+        );
+    }
+
+    #[test]
+    fn cache_keys_include_the_settings() {
+        let diarizer = HttpDiarizer::new("http://localhost:8000/diarize".to_string(), Some(3));
+        assert_eq!(
+            diarizer.cache_key(),
+            "diarize=http://localhost:8000/diarize|speakers=3"
+        );
+        let diarizer = HttpDiarizer::new("http://localhost:8000/diarize".to_string(), None);
+        assert_eq!(
+            diarizer.cache_key(),
+            "diarize=http://localhost:8000/diarize|speakers="
+        );
+    }
+}
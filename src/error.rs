@@ -0,0 +1,286 @@
+use reqwest::StatusCode;
+use serde_json::Value;
+use thiserror::Error;
+
+/// Errors returned by the library.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The transcription API rejected a request.
+    #[error(transparent)]
+    Api(#[from] ApiError),
+
+    /// yt-dlp, ffmpeg or a local engine exited with an error.
+    #[error(transparent)]
+    Process(#[from] ProcessError),
+
+    /// Anything else, e.g. an unreadable file or an unreachable server.
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// The API error behind this error, if any.
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            Error::Api(api_error) => Some(api_error),
+            _ => None,
+        }
+    }
+}
+
+/// Recovers the typed errors from the internal `anyhow` chains; their
+/// messages already say what failed.
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<Error>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<ApiError>() {
+            Ok(api_error) => return Error::Api(api_error),
+            Err(error) => error,
+        };
+        match error.downcast::<ProcessError>() {
+            Ok(process_error) => Error::Process(process_error),
+            Err(error) => Error::Other(error),
+        }
+    }
+}
+
+/// A program the pipeline runs failed, with what it wrote to stderr.
+#[derive(Debug, Error)]
+#[error("{program} failed: {message}")]
+pub struct ProcessError {
+    pub program: String,
+    pub message: String,
+}
+
+impl ProcessError {
+    pub(crate) fn new(program: &str, stderr: impl AsRef<[u8]>) -> Self {
+        ProcessError {
+            program: program.to_string(),
+            message: String::from_utf8_lossy(stderr.as_ref()).into_owned(),
+        }
+    }
+}
+
+/// A transcription request rejected by the API, classified from the HTTP
+/// status and the `{"error": {"message", "type", "code"}}` body that
+/// OpenAI-compatible servers return.
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("authentication failed: {0}")]
+    Auth(String),
+
+    #[error("quota or rate limit exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("file too large: {0}")]
+    FileTooLarge(String),
+
+    #[error("unsupported media: {0}")]
+    UnsupportedMedia(String),
+
+    #[error("server error ({status}): {message}")]
+    Server { status: StatusCode, message: String },
+
+    #[error("request failed ({status}): {message}")]
+    Other { status: StatusCode, message: String },
+}
+
+impl ApiError {
+    pub fn from_response(status: StatusCode, body: &str) -> Self {
+        let json: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+        let error = &json["error"];
+        let message = error["message"]
+            .as_str()
+            .or_else(|| error.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| body.trim().to_string());
+        let kind = format!(
+            "{} {}",
+            error["type"].as_str().unwrap_or_default(),
+            error["code"].as_str().unwrap_or_default()
+        )
+        .to_lowercase();
+        let lower_message = message.to_lowercase();
+
+        // Server errors often come with an HTML error page from a proxy, so
+        // only the bodies of rejected requests are looked at
+        match status {
+            _ if status.is_server_error() => ApiError::Server { status, message },
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ApiError::Auth(message),
+            StatusCode::TOO_MANY_REQUESTS => ApiError::QuotaExceeded(message),
+            StatusCode::PAYLOAD_TOO_LARGE => ApiError::FileTooLarge(message),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => ApiError::UnsupportedMedia(message),
+            _ if !status.is_client_error() => ApiError::Other { status, message },
+            _ if kind.contains("invalid_api_key") => ApiError::Auth(message),
+            _ if kind.contains("quota") => ApiError::QuotaExceeded(message),
+            _ if kind.contains("too_large") || lower_message.contains("too large") => {
+                ApiError::FileTooLarge(message)
+            }
+            _ if lower_message.contains("file format")
+                || lower_message.contains("media")
+                || lower_message.contains("could not process file") =>
+            {
+                ApiError::UnsupportedMedia(message)
+            }
+            _ => ApiError::Other { status, message },
+        }
+    }
+
+    /// Process exit code for this category:
+    ///
+    /// | code | meaning                    |
+    /// |------|----------------------------|
+    /// | 3    | authentication failed      |
+    /// | 4    | quota or rate limit        |
+    /// | 5    | file too large             |
+    /// | 6    | unsupported media          |
+    /// | 7    | server error               |
+    /// | 8    | other rejected request     |
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ApiError::Auth(_) => 3,
+            ApiError::QuotaExceeded(_) => 4,
+            ApiError::FileTooLarge(_) => 5,
+            ApiError::UnsupportedMedia(_) => 6,
+            ApiError::Server { .. } => 7,
+            ApiError::Other { .. } => 8,
+        }
+    }
+
+    /// What the user can do about it.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            ApiError::Auth(_) => "Check that the API key for the selected --backend is set and valid.",
+            ApiError::QuotaExceeded(_) => {
+                "Wait for the rate limit to reset, raise --max-retries, lower --max-uploads, or check your plan's quota."
+            }
+            ApiError::FileTooLarge(_) => {
+                "The audio chunk exceeds the backend's upload limit; try a lower bitrate or a shorter clip."
+            }
+            ApiError::UnsupportedMedia(_) => {
+                "The backend could not decode the audio; check that ffmpeg produced a valid file."
+            }
+            ApiError::Server { .. } => {
+                "The transcription service is having trouble; try again later."
+            }
+            ApiError::Other { .. } => "Check the request options and the backend's documentation.",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn statuses_pick_the_category() {
+        let error = |status| ApiError::from_response(status, r#"{"error": {"message": "Nope"}}"#);
+        assert!(matches!(error(StatusCode::UNAUTHORIZED), ApiError::Auth(m) if m == "Nope"));
+        assert!(matches!(error(StatusCode::FORBIDDEN), ApiError::Auth(_)));
+        assert!(matches!(
+            error(StatusCode::TOO_MANY_REQUESTS),
+            ApiError::QuotaExceeded(_)
+        ));
+        assert!(matches!(
+            error(StatusCode::PAYLOAD_TOO_LARGE),
+            ApiError::FileTooLarge(_)
+        ));
+        assert!(matches!(
+            error(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+            ApiError::UnsupportedMedia(_)
+        ));
+        assert!(matches!(
+            error(StatusCode::SERVICE_UNAVAILABLE),
+            ApiError::Server { .. }
+        ));
+        assert!(matches!(
+            error(StatusCode::BAD_REQUEST),
+            ApiError::Other {
+                status: StatusCode::BAD_REQUEST,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn rejected_requests_are_classified_by_body() {
+        let key = r#"{"error": {"message": "Bad key", "type": "invalid_request_error", "code": "invalid_api_key"}}"#;
+        assert!(matches!(
+            ApiError::from_response(StatusCode::BAD_REQUEST, key),
+            ApiError::Auth(m) if m == "Bad key"
+        ));
+        let quota = r#"{"error": {"message": "Out of credits", "type": "insufficient_quota"}}"#;
+        assert!(matches!(
+            ApiError::from_response(StatusCode::BAD_REQUEST, quota),
+            ApiError::QuotaExceeded(_)
+        ));
+        let size = r#"{"error": {"message": "Request entity too large"}}"#;
+        assert!(matches!(
+            ApiError::from_response(StatusCode::BAD_REQUEST, size),
+            ApiError::FileTooLarge(_)
+        ));
+        let format =
+            r#"{"error": {"message": "Invalid file format. Supported formats: flac, mp3"}}"#;
+        assert!(matches!(
+            ApiError::from_response(StatusCode::BAD_REQUEST, format),
+            ApiError::UnsupportedMedia(_)
+        ));
+    }
+
+    #[test]
+    fn server_errors_ignore_the_body() {
+        let page =
+            "<html><style>@media (max-width: 600px) {}</style>Bad gateway: file too large</html>";
+        let error = ApiError::from_response(StatusCode::BAD_GATEWAY, page);
+        assert!(matches!(
+            error,
+            ApiError::Server {
+                status: StatusCode::BAD_GATEWAY,
+                ..
+            }
+        ));
+        assert_eq!(error.exit_code(), 7);
+    }
+
+    #[test]
+    fn plain_bodies_become_the_message() {
+        let error = ApiError::from_response(StatusCode::BAD_REQUEST, "  model not found\n");
+        assert!(matches!(error, ApiError::Other { message, .. } if message == "model not found"));
+    }
+
+    #[test]
+    fn typed_errors_survive_context() {
+        let api: anyhow::Result<()> =
+            Err(ApiError::Auth("Invalid API key".to_string())).context("Failed to transcribe");
+        assert!(matches!(
+            Error::from(api.unwrap_err()),
+            Error::Api(ApiError::Auth(_))
+        ));
+
+        let process: anyhow::Result<()> =
+            Err(ProcessError::new("ffmpeg", "No such file")).context("Failed to convert");
+        match Error::from(process.unwrap_err()) {
+            Error::Process(e) => assert_eq!(e.program, "ffmpeg"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn library_errors_are_not_wrapped_twice() {
+        let error = anyhow::Error::from(Error::from(ApiError::QuotaExceeded(String::new())));
+        assert!(matches!(
+            Error::from(error),
+            Error::Api(ApiError::QuotaExceeded(_))
+        ));
+
+        let other = Error::from(anyhow::anyhow!("Something else"));
+        assert!(matches!(other, Error::Other(_)));
+        assert!(other.api_error().is_none());
+    }
+}
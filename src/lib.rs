@@ -0,0 +1,51 @@
+//! Downloads audio with yt-dlp, converts it with ffmpeg and transcribes it
+//! with Whisper, hosted or local.
+//!
+//! [`Pipeline`] does all of it for [`batch::Item`]s, with caching, captions,
+//! clipping and speaker labels; [`download_audio`], [`convert_audio`] and
+//! the [`Transcriber`] backends can also be used on their own.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use yt_dlts::{backend::OpenAiCompatible, batch, Pipeline};
+//!
+//! # async fn run() -> yt_dlts::Result<()> {
+//! let transcriber = OpenAiCompatible::new(
+//!     "local-server",
+//!     "http://localhost:8080/v1".to_string(),
+//!     None,
+//!     "whisper-1".to_string(),
+//!     5,
+//!     Default::default(),
+//! );
+//! let pipeline = Pipeline::builder(Arc::new(transcriber)).build()?;
+//! for (i, item) in batch::resolve("talk.mp3").await?.iter().enumerate() {
+//!     println!("{}", pipeline.transcribe(item, i + 1).await?.text);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(clippy::all)]
+
+pub mod audio;
+pub mod backend;
+pub mod batch;
+pub mod cache;
+pub mod captions;
+mod chunk;
+pub mod clip;
+pub mod diarize;
+pub mod error;
+pub mod local;
+pub mod pipeline;
+pub mod profile;
+pub mod progress;
+mod retry;
+pub mod stream;
+pub mod transcript;
+
+pub use audio::{convert_audio, download_audio};
+pub use backend::Transcriber;
+pub use error::{ApiError, Error, ProcessError, Result};
+pub use pipeline::{Pipeline, PipelineBuilder};
@@ -0,0 +1,369 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use clap::ValueEnum;
+use serde_json::Value;
+use std::{path::Path, sync::Arc};
+use tokio::process::Command;
+
+use crate::backend::{RequestOptions, Transcriber};
+use crate::error::{ProcessError, Result};
+use crate::profile::Profile;
+use crate::progress::Observer;
+use crate::transcript::{Detail, Segment, Transcript, Word};
+
+/// Program used by the local backend.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Engine {
+    /// whisper.cpp's whisper-cli
+    WhisperCpp,
+    /// faster-whisper through whisper-ctranslate2
+    FasterWhisper,
+}
+
+/// Runs whisper.cpp or faster-whisper on this machine, so no audio leaves it.
+pub struct Local {
+    engine: Engine,
+    program: String,
+    /// Path to the model file or directory.
+    model: String,
+    threads: usize,
+    request: RequestOptions,
+}
+
+impl Local {
+    /// Runs `engine` with the model file (whisper.cpp) or converted model
+    /// directory (faster-whisper) at `model_path`, so nothing is downloaded.
+    pub fn new(engine: Engine, model_path: String) -> Self {
+        let program = match engine {
+            Engine::WhisperCpp => "whisper-cli",
+            Engine::FasterWhisper => "whisper-ctranslate2",
+        };
+        Local {
+            engine,
+            program: program.to_string(),
+            model: model_path,
+            threads: std::thread::available_parallelism().map_or(4, |n| n.get()),
+            request: RequestOptions::default(),
+        }
+    }
+
+    /// Path to the engine's executable (`whisper-cli` or
+    /// `whisper-ctranslate2` on the `PATH`).
+    pub fn with_program(mut self, program: String) -> Self {
+        self.program = program;
+        self
+    }
+
+    /// CPU threads to use (all cores).
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Options applied to every transcription (none).
+    pub fn with_request(mut self, request: RequestOptions) -> Self {
+        self.request = request;
+        self
+    }
+
+    /// Runs whisper-cli with JSON output next to the audio file. Word
+    /// timings need the full JSON output, which includes every token.
+    async fn whisper_cpp(&self, file_path: &str, detail: Detail) -> Result<Transcript> {
+        let prefix = format!("{}.whisper", file_path);
+        let mut command = Command::new(&self.program);
+        command
+            .args(["-m", &self.model, "-f", file_path, "-of", &prefix])
+            .args(["-t", &self.threads.to_string(), "-oj", "-np"])
+            .args(["-l", self.request.language.as_deref().unwrap_or("auto")]);
+        if self.request.translate {
+            command.arg("-tr");
+        }
+        if let Some(prompt) = &self.request.prompt {
+            command.args(["--prompt", prompt]);
+        }
+        if let Some(temperature) = self.request.temperature {
+            command.args(["-tp", &temperature.to_string()]);
+        }
+        if detail == Detail::Words {
+            command.arg("-ojf");
+        }
+        self.run(command).await?;
+
+        let output = format!("{}.json", prefix);
+        let json = read_json(&output).await?;
+        tokio::fs::remove_file(&output).await.ok();
+        Ok(parse_whisper_cpp(&json))
+    }
+
+    /// Runs whisper-ctranslate2, which writes openai-whisper's JSON format
+    /// next to the audio file.
+    async fn faster_whisper(&self, file_path: &str, detail: Detail) -> Result<Transcript> {
+        let path = Path::new(file_path);
+        let output_dir = path
+            .parent()
+            .and_then(Path::to_str)
+            .filter(|dir| !dir.is_empty())
+            .unwrap_or(".");
+        let mut command = Command::new(&self.program);
+        command
+            .arg(file_path)
+            .args(["--model_directory", &self.model, "--device", "cpu"])
+            .args(["--threads", &self.threads.to_string()])
+            .args(["--output_format", "json", "--output_dir", output_dir])
+            .args(["--verbose", "False"])
+            // The model is on disk, so never try to download anything
+            .env("HF_HUB_OFFLINE", "1");
+        if let Some(language) = &self.request.language {
+            command.args(["--language", language]);
+        }
+        if self.request.translate {
+            command.args(["--task", "translate"]);
+        }
+        if let Some(prompt) = &self.request.prompt {
+            command.args(["--initial_prompt", prompt]);
+        }
+        if let Some(temperature) = self.request.temperature {
+            command.args(["--temperature", &temperature.to_string()]);
+        }
+        if detail == Detail::Words {
+            command.args(["--word_timestamps", "True"]);
+        }
+        self.run(command).await?;
+
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .context("Audio file name is not valid UTF-8")?;
+        let output = Path::new(output_dir).join(format!("{}.json", stem));
+        let output = output.to_str().context("Output path is not valid UTF-8")?;
+        let json = read_json(output).await?;
+        tokio::fs::remove_file(output).await.ok();
+        parse_faster_whisper(json)
+    }
+
+    async fn run(&self, mut command: Command) -> Result<()> {
+        let output = command
+            .output()
+            .await
+            .with_context(|| format!("Failed to execute {}", self.program))?;
+
+        if !output.status.success() {
+            return Err(ProcessError::new(&self.program, &output.stderr).into());
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Transcriber for Local {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// whisper.cpp only reads 16 kHz WAV unless it was built with ffmpeg.
+    fn default_profile(&self) -> Profile {
+        Profile::WavPcm16
+    }
+
+    /// There is no upload limit, and one long run beats many chunks
+    /// competing for the same CPU cores.
+    fn needs_chunking(&self) -> bool {
+        false
+    }
+
+    fn cache_key(&self) -> String {
+        self.request.cache_key()
+    }
+
+    async fn transcribe(
+        &self,
+        file_path: &str,
+        detail: Detail,
+        _observer: Arc<dyn Observer>,
+    ) -> Result<Transcript> {
+        match self.engine {
+            Engine::WhisperCpp => self.whisper_cpp(file_path, detail).await,
+            Engine::FasterWhisper => self.faster_whisper(file_path, detail).await,
+        }
+    }
+}
+
+async fn read_json(path: &str) -> Result<Value> {
+    let contents = tokio::fs::read(path)
+        .await
+        .context("Failed to read transcription output")?;
+    Ok(serde_json::from_slice(&contents).context("Failed to parse transcription output")?)
+}
+
+/// Converts whisper.cpp's `-oj` output, which gives segment offsets in
+/// milliseconds under `transcription`. With `-ojf`, words are put together
+/// from the tokens of each segment.
+fn parse_whisper_cpp(json: &Value) -> Transcript {
+    let segments: Vec<Segment> = json["transcription"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|s| Segment {
+            start: s["offsets"]["from"].as_f64().unwrap_or_default() / 1000.0,
+            end: s["offsets"]["to"].as_f64().unwrap_or_default() / 1000.0,
+            text: s["text"].as_str().unwrap_or_default().trim().to_string(),
+            ..Default::default()
+        })
+        .collect();
+    let words = json["transcription"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|s| s["tokens"].as_array().into_iter().flatten())
+        .fold(Vec::new(), add_token);
+
+    Transcript {
+        text: segments
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+        language: json["result"]["language"].as_str().map(str::to_string),
+        duration: segments.last().map(|s| s.end),
+        segments,
+        words,
+        ..Default::default()
+    }
+}
+
+/// Adds a whisper.cpp token to `words`. Tokens starting with a space begin a
+/// new word, others continue the last one, and special tokens such as
+/// `[_BEG_]` or `[_TT_150]` are skipped.
+fn add_token(mut words: Vec<Word>, token: &Value) -> Vec<Word> {
+    let text = token["text"].as_str().unwrap_or_default();
+    if text.is_empty() || text.starts_with("[_") {
+        return words;
+    }
+    let start = token["offsets"]["from"].as_f64().unwrap_or_default() / 1000.0;
+    let end = token["offsets"]["to"].as_f64().unwrap_or_default() / 1000.0;
+    match words.last_mut() {
+        Some(word) if !text.starts_with(' ') => {
+            word.word.push_str(text);
+            word.end = end;
+        }
+        _ => words.push(Word {
+            word: text.trim_start().to_string(),
+            start,
+            end,
+        }),
+    }
+    words
+}
+
+/// Converts openai-whisper style JSON, moving the per-segment `words` up to
+/// the transcript like the HTTP APIs return them.
+fn parse_faster_whisper(json: Value) -> Result<Transcript> {
+    let mut transcript: Transcript =
+        serde_json::from_value(json).context("Failed to extract transcript from JSON")?;
+    for segment in &mut transcript.segments {
+        if let Some(words) = segment.extra.remove("words") {
+            let words: Vec<Word> = serde_json::from_value(words).unwrap_or_default();
+            transcript.words.extend(words);
+        }
+    }
+    if transcript.duration.is_none() {
+        transcript.duration = transcript.segments.last().map(|s| s.end);
+    }
+    Ok(transcript)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn token(text: &str, from: u64, to: u64) -> Value {
+        json!({"text": text, "offsets": {"from": from, "to": to}, "p": 0.9})
+    }
+
+    #[test]
+    fn parses_whisper_cpp_segments() {
+        let json = json!({
+            "result": {"language": "en"},
+            "transcription": [
+                {"offsets": {"from": 0, "to": 1500}, "text": " Hello there."},
+                {"offsets": {"from": 1500, "to": 3020}, "text": " General Kenobi."}
+            ]
+        });
+        let transcript = parse_whisper_cpp(&json);
+        assert_eq!(transcript.text, "Hello there. General Kenobi.");
+        assert_eq!(transcript.language.as_deref(), Some("en"));
+        assert_eq!(transcript.duration, Some(3.02));
+        assert_eq!(transcript.segments[1].start, 1.5);
+        assert!(transcript.words.is_empty());
+    }
+
+    #[test]
+    fn builds_words_from_whisper_cpp_tokens() {
+        let json = json!({
+            "transcription": [
+                {
+                    "offsets": {"from": 0, "to": 1500},
+                    "text": " Hello there.",
+                    "tokens": [
+                        token("[_BEG_]", 0, 0),
+                        token(" Hello", 0, 600),
+                        token(" there", 700, 1200),
+                        token(".", 1200, 1300),
+                        token("[_TT_75]", 1500, 1500)
+                    ]
+                },
+                {
+                    "offsets": {"from": 1500, "to": 3000},
+                    "text": " Kenobi.",
+                    "tokens": [
+                        token(" Ken", 1600, 1900),
+                        token("obi", 1900, 2400),
+                        token(".", 2400, 2500)
+                    ]
+                }
+            ]
+        });
+        let words: Vec<_> = parse_whisper_cpp(&json)
+            .words
+            .into_iter()
+            .map(|w| (w.word, w.start, w.end))
+            .collect();
+        assert_eq!(
+            words,
+            [
+                ("Hello".to_string(), 0.0, 0.6),
+                ("there.".to_string(), 0.7, 1.3),
+                ("Kenobi.".to_string(), 1.6, 2.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn moves_faster_whisper_words_up() {
+        let json = json!({
+            "text": " Hi all.",
+            "language": "en",
+            "segments": [{
+                "id": 0,
+                "start": 0.0,
+                "end": 1.2,
+                "text": " Hi all.",
+                "words": [
+                    {"word": " Hi", "start": 0.0, "end": 0.4, "probability": 0.9},
+                    {"word": " all.", "start": 0.5, "end": 1.2, "probability": 0.8}
+                ]
+            }]
+        });
+        let transcript = parse_faster_whisper(json).unwrap();
+        assert_eq!(transcript.words.len(), 2);
+        assert_eq!(transcript.words[1].start, 0.5);
+        assert!(!transcript.segments[0].extra.contains_key("words"));
+        assert_eq!(transcript.duration, Some(1.2));
+    }
+}
@@ -1,148 +1,841 @@
 #![warn(clippy::all)]
 
 use anyhow::{Context, Result};
-use clap::Parser;
-use reqwest::multipart::{Form, Part};
-use serde_json::Value;
-use tokio::{
-    fs::{remove_file, File},
-    io::AsyncWriteExt,
-    process::Command,
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
+use std::{path::PathBuf, process::ExitCode, sync::Arc, time::Duration};
+use tokio::{fs::File, io::AsyncWriteExt, task::JoinSet};
+use yt_dlts::{
+    backend::{self, OpenAiCompatible, RequestOptions},
+    batch::{self, Source},
+    cache::Cache,
+    captions::Captions,
+    clip::{self, Clip, Range},
+    diarize::{Diarizer, HttpDiarizer},
+    local::{Engine, Local},
+    profile::{Encoding, Preprocessing, Profile},
+    progress::{EventFormat, Reporter},
+    transcript::{Detail, Format, RenderOptions, Transcript},
+    ApiError, Error, Pipeline, Transcriber,
 };
 
+/// Transcribe YouTube videos and other media with Whisper.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// YouTube video URL
-    url: String,
+    #[command(subcommand)]
+    command: Option<Commands>,
 
-    /// Output text file name (optional)
+    /// YouTube video, playlist or channel URLs, local media files, direct
+    /// media links, or `-` to read media from standard input
+    #[arg(required_unless_present = "batch_file")]
+    urls: Vec<String>,
+
+    /// File with one URL per line
+    #[arg(long)]
+    batch_file: Option<String>,
+
+    /// Output text file name (optional, single video only)
     #[arg(short, long)]
     output: Option<String>,
+
+    /// Output file name template with {id}, {title}, {index} and {ext}
+    /// placeholders [default for several videos: "{title} [{id}].{ext}"]
+    #[arg(short = 'O', long)]
+    output_template: Option<String>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = Format::Txt)]
+    format: Format,
+
+    /// Pause in seconds that starts a new paragraph in md and html output
+    #[arg(long, default_value_t = 2.0)]
+    paragraph_gap: f64,
+
+    /// Group the text under the video's chapter headings
+    #[arg(long)]
+    split_chapters: bool,
+
+    /// Write each chapter to its own file next to the output file
+    #[arg(long, requires = "split_chapters")]
+    chapter_files: bool,
+
+    /// Add the video's title, uploader, upload date, URL and the backend
+    /// used to the output
+    #[arg(long)]
+    with_metadata: bool,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+
+    #[command(flatten)]
+    captions: CaptionArgs,
+
+    #[command(flatten)]
+    convert: ConvertArgs,
+
+    #[command(flatten)]
+    clip: ClipArgs,
+
+    #[command(flatten)]
+    diarize: DiarizeArgs,
+
+    /// Number of videos downloaded and converted at the same time
+    #[arg(short, long, default_value_t = 2)]
+    jobs: usize,
+
+    /// Number of transcription requests in flight at the same time
+    #[arg(long, default_value_t = 2)]
+    max_uploads: usize,
+
+    /// Pipe YouTube audio from yt-dlp through ffmpeg into the upload without
+    /// temporary files. Long videos must fit the upload limit in one piece,
+    /// and failed uploads are not retried. Not available with --backend local
+    #[arg(long, conflicts_with = "diarize")]
+    stream: bool,
+
+    /// Keep the temporary working directory for debugging
+    #[arg(long)]
+    keep_temp: bool,
+
+    /// Write events in this format to stderr instead of messages and
+    /// progress, for programs running yt-dlts
+    #[arg(long, value_enum)]
+    events: Option<EventFormat>,
+
+    /// Neither read nor write the transcript cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Ignore cached transcripts and replace them with fresh ones
+    #[arg(long, conflicts_with = "no_cache")]
+    refresh: bool,
 }
 
-async fn download_audio(url: &str, output_file: &str) -> Result<()> {
-    let output = Command::new("yt-dlp")
-        .args(["-f", "bestaudio", "-N8", "-o", output_file, url])
-        .output()
-        .await
-        .context("Failed to execute yt-dlp")?;
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Manage the transcript cache
+    #[command(subcommand)]
+    Cache(CacheCommand),
+}
+
+/// `yt-dlts cache ...` subcommands.
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// List cached transcripts
+    Ls,
+    /// Remove cached transcripts
+    Prune {
+        /// Only remove entries older than this, e.g. 30d, 12h or 2w
+        #[arg(long)]
+        older_than: String,
+    },
+}
+
+/// Transcription service selected with `--backend`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Backend {
+    /// Groq (GROQ_API_KEY)
+    Groq,
+    /// OpenAI (OPENAI_API_KEY)
+    Openai,
+    /// Any OpenAI-compatible server at --base-url (TRANSCRIBE_API_KEY, optional)
+    Compatible,
+    /// whisper.cpp or faster-whisper on this machine, without network access
+    Local,
+}
+
+/// Command-line options selecting and configuring the transcription backend.
+#[derive(clap::Args, Debug)]
+struct BackendArgs {
+    /// Transcription backend
+    #[arg(short, long, value_enum, default_value_t = Backend::Groq)]
+    backend: Backend,
+
+    /// Base URL of the transcription API (required for the compatible backend)
+    #[arg(long)]
+    base_url: Option<String>,
+
+    /// Model name (defaults to the backend's recommended model)
+    #[arg(short, long)]
+    model: Option<String>,
 
-    if !output.status.success() {
-        anyhow::bail!("yt-dlp failed: {}", String::from_utf8_lossy(&output.stderr));
+    /// How often a rate-limited or failed request is retried
+    #[arg(long, default_value_t = backend::DEFAULT_MAX_RETRIES)]
+    max_retries: u32,
+
+    #[command(flatten)]
+    request: RequestArgs,
+
+    #[command(flatten)]
+    local: LocalArgs,
+}
+
+impl BackendArgs {
+    /// Builds the selected transcriber.
+    fn transcriber(&self) -> Result<Box<dyn Transcriber>> {
+        let request = self.request.options()?;
+        let hosted = |transcriber: OpenAiCompatible| {
+            let mut transcriber = transcriber
+                .with_max_retries(self.max_retries)
+                .with_request(request.clone());
+            if let Some(base_url) = &self.base_url {
+                transcriber = transcriber.with_base_url(base_url.clone());
+            }
+            if let Some(model) = &self.model {
+                transcriber = transcriber.with_model(model.clone());
+            }
+            transcriber
+        };
+
+        Ok(match self.backend {
+            Backend::Groq => {
+                let api_key = std::env::var("GROQ_API_KEY").context("GROQ_API_KEY not set")?;
+                Box::new(hosted(OpenAiCompatible::groq(api_key)))
+            }
+            Backend::Openai => {
+                let api_key = std::env::var("OPENAI_API_KEY").context("OPENAI_API_KEY not set")?;
+                Box::new(hosted(OpenAiCompatible::openai(api_key)))
+            }
+            Backend::Compatible => {
+                let base_url = self
+                    .base_url
+                    .clone()
+                    .context("--base-url is required for the compatible backend")?;
+                Box::new(OpenAiCompatible::new(
+                    "compatible",
+                    base_url,
+                    std::env::var("TRANSCRIBE_API_KEY").ok(),
+                    self.model
+                        .clone()
+                        .unwrap_or_else(|| "whisper-1".to_string()),
+                    self.max_retries,
+                    request,
+                ))
+            }
+            Backend::Local => {
+                if self.model.is_some() {
+                    anyhow::bail!(
+                        "The local backend takes the model with --model-path instead of --model"
+                    );
+                }
+                let model_path = self
+                    .local
+                    .model_path
+                    .clone()
+                    .context("--model-path is required for the local backend")?;
+                let mut local = Local::new(self.local.engine, model_path).with_request(request);
+                if let Some(program) = &self.local.whisper_bin {
+                    local = local.with_program(program.clone());
+                }
+                if let Some(threads) = self.local.threads {
+                    local = local.with_threads(threads);
+                }
+                Box::new(local)
+            }
+        })
     }
+}
 
-    Ok(())
+/// Options forwarded to the Whisper API with every request.
+#[derive(clap::Args, Clone, Debug)]
+struct RequestArgs {
+    /// Spoken language as an ISO-639-1 code, e.g. de or ja (auto-detected if omitted)
+    #[arg(short, long)]
+    language: Option<String>,
+
+    /// Text that guides spelling and style, e.g. product names and jargon
+    #[arg(long)]
+    prompt: Option<String>,
+
+    /// Sampling temperature between 0 and 1
+    #[arg(long)]
+    temperature: Option<f32>,
+
+    /// Translate the speech into English instead of transcribing it
+    #[arg(long)]
+    translate: bool,
 }
 
-async fn convert_audio(input_file: &str, output_file: &str) -> Result<()> {
-    let output = Command::new("ffmpeg")
-        .args([
-            "-i",
-            input_file,
-            "-c:a",
-            "libopus",
-            "-b:a",
-            "24k",
-            "-ar",
-            "16000",
-            "-ac",
-            "1",
-            "-map",
-            "0:a:",
-            "-vn",
-            output_file,
-        ])
-        .output()
-        .await
-        .context("Failed to execute ffmpeg")?;
+impl RequestArgs {
+    fn options(&self) -> Result<RequestOptions> {
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=1.0).contains(&temperature) {
+                anyhow::bail!("--temperature must be between 0 and 1");
+            }
+        }
+        Ok(RequestOptions {
+            language: self.language.clone(),
+            prompt: self.prompt.clone(),
+            temperature: self.temperature,
+            translate: self.translate,
+        })
+    }
+}
+
+/// Command-line options for the local backend.
+#[derive(clap::Args, Clone, Debug)]
+struct LocalArgs {
+    /// Program the local backend runs
+    #[arg(long, value_enum, default_value_t = Engine::WhisperCpp)]
+    engine: Engine,
+
+    /// Path to the engine's executable [default: whisper-cli or whisper-ctranslate2]
+    #[arg(long)]
+    whisper_bin: Option<String>,
+
+    /// Model file (whisper.cpp) or converted model directory (faster-whisper),
+    /// required by the local backend so nothing is downloaded
+    #[arg(long)]
+    model_path: Option<String>,
+
+    /// CPU threads used by the local backend [default: all cores]
+    #[arg(long)]
+    threads: Option<usize>,
+}
 
-    if !output.status.success() {
-        anyhow::bail!("ffmpeg failed: {}", String::from_utf8_lossy(&output.stderr));
+/// Command-line options for reusing captions that already exist on the video.
+#[derive(clap::Args, Clone, Debug)]
+struct CaptionArgs {
+    /// Use the video's own subtitles instead of transcribing when available
+    #[arg(long)]
+    prefer_captions: bool,
+
+    /// Subtitle language to look for with --prefer-captions
+    #[arg(long, default_value = "en")]
+    captions_lang: String,
+
+    /// Also accept auto-generated captions with --prefer-captions
+    #[arg(long)]
+    allow_auto_captions: bool,
+}
+
+impl CaptionArgs {
+    /// The captions to look for, if `--prefer-captions` is set.
+    fn captions(&self) -> Option<Captions> {
+        self.prefer_captions.then(|| Captions {
+            lang: self.captions_lang.clone(),
+            allow_auto: self.allow_auto_captions,
+        })
     }
+}
 
-    Ok(())
+/// Command-line options controlling how the audio is encoded.
+#[derive(clap::Args, Debug)]
+struct ConvertArgs {
+    /// Conversion profile (defaults to the backend's preferred profile)
+    #[arg(short, long, value_enum)]
+    profile: Option<Profile>,
+
+    /// Audio bitrate for lossy profiles, e.g. 32k
+    #[arg(long)]
+    audio_bitrate: Option<String>,
+
+    /// Sample rate in Hz
+    #[arg(long)]
+    sample_rate: Option<u32>,
+
+    /// Normalize loudness (ffmpeg loudnorm)
+    #[arg(long)]
+    normalize: bool,
+
+    /// Cut silences longer than a second (ffmpeg silenceremove); timestamps
+    /// then no longer line up with the original video
+    #[arg(long)]
+    trim_silence: bool,
+
+    /// Remove rumble and background noise (ffmpeg highpass and afftdn)
+    #[arg(long)]
+    denoise: bool,
+}
+
+impl ConvertArgs {
+    /// The encoding for these options, falling back to `transcriber`'s
+    /// preferred profile.
+    fn encoding(&self, transcriber: &dyn Transcriber) -> Result<Encoding> {
+        let preprocessing = Preprocessing {
+            normalize: self.normalize,
+            trim_silence: self.trim_silence,
+            denoise: self.denoise,
+        };
+        Ok(Encoding::new(
+            self.profile
+                .unwrap_or_else(|| transcriber.default_profile()),
+            self.audio_bitrate.clone(),
+            self.sample_rate,
+            &preprocessing.filters(),
+        )?)
+    }
+}
+
+/// Command-line options restricting transcription to part of a video.
+#[derive(clap::Args, Clone, Debug, Default)]
+struct ClipArgs {
+    /// Only transcribe from this time on, e.g. 12:00, 1:02:03 or 90
+    #[arg(long)]
+    start: Option<String>,
+
+    /// Only transcribe up to this time
+    #[arg(long)]
+    end: Option<String>,
+
+    /// Only transcribe this chapter, by title or number (starting at 1)
+    #[arg(long, conflicts_with_all = ["start", "end"])]
+    chapter: Option<String>,
+}
+
+impl ClipArgs {
+    /// The part of every video these options select, or `None` for all of it.
+    fn clip(&self) -> Result<Option<Clip>> {
+        if let Some(chapter) = &self.chapter {
+            return Ok(Some(Clip::Chapter(chapter.clone())));
+        }
+
+        let start = match &self.start {
+            Some(start) => {
+                clip::parse_time(start).with_context(|| format!("Invalid --start: {}", start))?
+            }
+            None => 0.0,
+        };
+        let end = match &self.end {
+            Some(end) => {
+                Some(clip::parse_time(end).with_context(|| format!("Invalid --end: {}", end))?)
+            }
+            None => None,
+        };
+        if end.is_some_and(|end| end <= start) {
+            anyhow::bail!("--end must be after --start");
+        }
+
+        Ok((start > 0.0 || end.is_some()).then_some(Clip::Range(Range { start, end })))
+    }
 }
 
-async fn transcribe_audio(file_path: &str) -> Result<String> {
-    let client = reqwest::Client::new();
-    let url = "https://api.groq.com/openai/v1/audio/transcriptions";
-    let api_key = std::env::var("GROQ_API_KEY").context("GROQ_API_KEY not set")?;
+/// Command-line options for labelling speakers.
+#[derive(clap::Args, Debug)]
+struct DiarizeArgs {
+    /// Label speakers using a diarization service (not applied to captions)
+    #[arg(long)]
+    diarize: bool,
 
-    let file_bytes = tokio::fs::read(file_path)
+    /// Endpoint of the diarization service
+    #[arg(long, default_value = "http://localhost:8000/diarize")]
+    diarization_url: String,
+
+    /// Number of speakers, if known, to help the diarization
+    #[arg(long, requires = "diarize")]
+    speakers: Option<u32>,
+}
+
+impl DiarizeArgs {
+    /// The diarizer to label speakers with, if `--diarize` is set.
+    fn diarizer(&self) -> Option<Box<dyn Diarizer>> {
+        if !self.diarize {
+            return None;
+        }
+        Some(Box::new(HttpDiarizer::new(
+            self.diarization_url.clone(),
+            self.speakers,
+        )))
+    }
+}
+
+/// How transcripts are written, shared by every item.
+struct Output {
+    render: RenderOptions,
+    chapter_files: bool,
+}
+
+impl Output {
+    fn render(&self, transcript: &Transcript) -> String {
+        transcript.render(&self.render)
+    }
+
+    /// Saves the rendered transcript, or one file per chapter with
+    /// `--chapter-files`, and returns the paths written.
+    async fn save(&self, transcript: &Transcript, output_file: &str) -> Result<Vec<String>> {
+        if !self.chapter_files || transcript.chapters.is_empty() {
+            save_transcript(output_file, &self.render(transcript)).await?;
+            return Ok(vec![output_file.to_string()]);
+        }
+
+        let mut paths = Vec::new();
+        for (i, chapter) in transcript.chapters.iter().enumerate() {
+            let part = transcript.chapter(chapter);
+            if part.segments.is_empty() {
+                continue;
+            }
+            let path = batch::chapter_path(output_file, i + 1, &chapter.title);
+            save_transcript(&path, &self.render(&part)).await?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+async fn save_transcript(output_file: &str, transcript: &str) -> Result<()> {
+    let mut file = File::create(output_file)
         .await
-        .context("Failed to read audio file")?;
-    let file_part = Part::bytes(file_bytes).file_name(file_path.to_string());
-
-    let form = Form::new()
-        .part("file", file_part)
-        .text("model", "whisper-large-v3")
-        .text("response_format", "json");
-
-    let response = client
-        .post(url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .multipart(form)
-        .send()
+        .context("Failed to create output file")?;
+    file.write_all(transcript.as_bytes())
         .await
-        .context("Failed to send request")?;
+        .context("Failed to write transcript to file")?;
+    Ok(())
+}
 
-    let json: Value = response
-        .json()
-        .await
-        .context("Failed to parse JSON response")?;
-    let transcript = json["text"]
-        .as_str()
-        .context("Failed to extract transcript from JSON")?
-        .to_string();
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut args = Args::parse();
+    // Which backends can stream depends on a value, which clap cannot express
+    if args.stream && args.backend.backend == Backend::Local {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--stream cannot be used with --backend local, which reads the audio from a file",
+            )
+            .exit();
+    }
+    let reporter = Reporter::new(args.events);
+
+    let result = match args.command.take() {
+        Some(Commands::Cache(command)) => run_cache_command(command).await,
+        None => run_in_workspace(args, reporter.clone()).await,
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            // API failures get their own exit code so scripts can tell them apart
+            let api_error = api_error(&e);
+            reporter.error(&e, api_error.map(ApiError::hint));
+            match api_error {
+                Some(api_error) => ExitCode::from(api_error.exit_code()),
+                None => ExitCode::FAILURE,
+            }
+        }
+    }
+}
 
-    Ok(transcript)
+/// The API error behind `error`, if any, also when it comes wrapped in the
+/// library's error type.
+fn api_error(error: &anyhow::Error) -> Option<&ApiError> {
+    error.chain().find_map(|cause| {
+        cause
+            .downcast_ref::<ApiError>()
+            .or_else(|| cause.downcast_ref::<Error>().and_then(Error::api_error))
+    })
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
-
-    // Download audio
-    let audio_file = "temp_audio.webm";
-    download_audio(&args.url, audio_file).await?;
-
-    // Convert audio
-    let converted_audio = "converted_audio.webm";
-    convert_audio(audio_file, converted_audio).await?;
-
-    // Transcribe audio
-    let transcript = transcribe_audio(converted_audio).await?;
-
-    if let Some(output_file) = args.output {
-        // Save transcript to file
-        let mut file = File::create(&output_file)
-            .await
-            .context("Failed to create output file")?;
-        file.write_all(transcript.as_bytes())
-            .await
-            .context("Failed to write transcript to file")?;
-        println!("Transcription completed. Output saved to {}", output_file);
+async fn run_in_workspace(args: Args, reporter: Arc<Reporter>) -> Result<()> {
+    let keep_temp = args.keep_temp;
+
+    let workspace = tempfile::Builder::new()
+        .prefix("yt-dlts-")
+        .tempdir()
+        .context("Failed to create temporary directory")?;
+    let workdir = workspace.path().to_path_buf();
+
+    // Dropping the workspace removes it, so make sure that happens on errors
+    // and Ctrl-C too rather than exiting from deep inside the pipeline
+    let result = tokio::select! {
+        result = run(args, workdir, reporter.clone()) => result,
+        _ = tokio::signal::ctrl_c() => Err(anyhow::anyhow!("Interrupted")),
+    };
+
+    if keep_temp {
+        let path = workspace.keep();
+        reporter.message(&format!("Temporary files kept in {}", path.display()));
+    }
+
+    result
+}
+
+async fn run(args: Args, workdir: PathBuf, reporter: Arc<Reporter>) -> Result<()> {
+    let transcriber: Arc<dyn Transcriber> = args.backend.transcriber()?.into();
+    let encoding = args.convert.encoding(transcriber.as_ref())?;
+    let detail = if args.split_chapters || args.diarize.diarize {
+        args.format.detail().max(Detail::Segments)
     } else {
-        // Print to stdout (and optionally copy to clipboard)
-        println!("Transcription:");
-        println!("{}", transcript);
-
-        {
-            use clipboard::{ClipboardContext, ClipboardProvider};
-            let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-            ctx.set_contents(transcript.clone()).unwrap();
-            println!("\nThe transcription has been copied to your clipboard.");
+        args.format.detail()
+    };
+    if args.convert.trim_silence && detail > Detail::Text {
+        reporter.warning("--trim-silence shifts timestamps relative to the original video");
+    }
+    let mut builder = Pipeline::builder(transcriber)
+        .jobs(args.jobs)
+        .max_uploads(args.max_uploads)
+        .detail(detail)
+        .encoding(encoding)
+        .refresh(args.refresh)
+        .stream(args.stream)
+        .workdir(workdir)
+        .keep_temp(args.keep_temp)
+        .reporter(reporter);
+    if let Some(captions) = args.captions.captions() {
+        builder = builder.captions(captions);
+    }
+    if let Some(clip) = args.clip.clip()? {
+        builder = builder.clip(clip);
+    }
+    if let Some(diarizer) = args.diarize.diarizer() {
+        builder = builder.diarizer(diarizer);
+    }
+    if !args.no_cache {
+        builder = builder.cache(Cache::open()?);
+    }
+    let pipeline = Arc::new(builder.build()?);
+    let output = Arc::new(Output {
+        render: RenderOptions {
+            format: args.format,
+            chapters: args.split_chapters,
+            metadata: args.with_metadata,
+            paragraph_gap: args.paragraph_gap,
+        },
+        chapter_files: args.chapter_files,
+    });
+    let mut urls = args.urls;
+    if let Some(batch_file) = &args.batch_file {
+        urls.extend(batch::read_batch_file(batch_file).await?);
+    }
+
+    let mut items = Vec::new();
+    for url in &urls {
+        items.extend(batch::resolve(url).await?);
+    }
+    if items.is_empty() {
+        anyhow::bail!("No videos to transcribe");
+    }
+    if items
+        .iter()
+        .filter(|item| item.source == Source::Stdin)
+        .count()
+        > 1
+    {
+        anyhow::bail!("Standard input (-) can only be given once");
+    }
+
+    // Messages go to stderr, so that stdout only ever carries the transcript
+    pipeline.reporter().message(&format!(
+        "Transcribing with {} ({})",
+        pipeline.transcriber().name(),
+        pipeline.transcriber().model()
+    ));
+
+    if items.len() == 1 && args.output_template.is_none() {
+        if args.chapter_files && args.output.is_none() {
+            anyhow::bail!("--chapter-files needs --output or --output-template");
+        }
+        let item = &items[0];
+        let tracker = pipeline.reporter().item(&item.title, &item.id, 1);
+        let result = async {
+            let transcript = pipeline.transcribe_tracked(item, 1, &tracker).await?;
+
+            let Some(output_file) = &args.output else {
+                let transcript = output.render(&transcript);
+                // Print to stdout (and optionally copy to clipboard)
+                pipeline.reporter().message("Transcription:");
+                println!("{}", transcript);
+
+                {
+                    use clipboard::{ClipboardContext, ClipboardProvider};
+                    // Headless machines have no clipboard, which must not
+                    // break wrappers reading the events
+                    let copied = ClipboardProvider::new()
+                        .and_then(|mut ctx: ClipboardContext| ctx.set_contents(transcript.clone()));
+                    match copied {
+                        Ok(()) => pipeline
+                            .reporter()
+                            .message("\nThe transcription has been copied to your clipboard."),
+                        Err(e) => pipeline
+                            .reporter()
+                            .warning(&format!("Failed to copy to the clipboard: {}", e)),
+                    }
+                }
+                return Ok(Vec::new());
+            };
+
+            // Save transcript to file
+            let paths = output.save(&transcript, output_file).await?;
+            pipeline.reporter().message(&format!(
+                "Transcription completed. Output saved to {}",
+                paths.join(", ")
+            ));
+            Ok(paths)
         }
+        .await;
+
+        return match result {
+            Ok(paths) => {
+                tracker.result(&item.title, &item.url, &paths);
+                Ok(())
+            }
+            Err(e) => {
+                tracker.error(&e);
+                Err(e)
+            }
+        };
     }
 
-    // Clean up temporary files
-    remove_file(audio_file)
-        .await
-        .context("Failed to remove temporary audio file")?;
-    remove_file(converted_audio)
-        .await
-        .context("Failed to remove converted audio file")?;
+    if args.output.is_some() {
+        anyhow::bail!("--output only works with a single video; use --output-template instead");
+    }
+    let template = args
+        .output_template
+        .as_deref()
+        .unwrap_or(batch::DEFAULT_TEMPLATE);
+
+    // Every item runs as its own task; the stage limits decide how many
+    // downloads, conversions and uploads actually overlap
+    let mut tasks = JoinSet::new();
+    for (i, item) in items.iter().cloned().enumerate() {
+        let pipeline = pipeline.clone();
+        let output = output.clone();
+        let output_file = batch::output_path(template, &item, i + 1, args.format);
+        tasks.spawn(async move {
+            let tracker = pipeline.reporter().item(&item.title, &item.id, i + 1);
+            let result = async {
+                let transcript = pipeline.transcribe_tracked(&item, i + 1, &tracker).await?;
+                output.save(&transcript, &output_file).await
+            }
+            .await;
+            match &result {
+                Ok(paths) => tracker.result(&item.title, &item.url, paths),
+                Err(e) => tracker.error(e),
+            }
+            (item, result)
+        });
+    }
+
+    let mut failures = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        let (item, result) = joined.context("Pipeline task panicked")?;
+        match result {
+            Ok(paths) => pipeline.reporter().message(&format!(
+                "{}: output saved to {}",
+                item.title,
+                paths.join(", ")
+            )),
+            Err(e) => {
+                pipeline
+                    .reporter()
+                    .message(&format!("Failed to transcribe {}: {:#}", item.url, e));
+                failures.push(e);
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        let summary = format!("{} of {} videos failed", failures.len(), items.len());
+        // Keep the API error (and its exit code) when every item failed for
+        // the same reason, e.g. a bad API key
+        let api_error_code = |e: &anyhow::Error| api_error(e).map(ApiError::exit_code);
+        let first_code = api_error_code(&failures[0]);
+        if first_code.is_some() && failures.iter().all(|e| api_error_code(e) == first_code) {
+            return Err(failures.swap_remove(0).context(summary));
+        }
+        anyhow::bail!(summary);
+    }
+    pipeline.reporter().message("Transcription completed.");
 
     Ok(())
 }
+
+async fn run_cache_command(command: CacheCommand) -> Result<()> {
+    let cache = Cache::open()?;
+    match command {
+        CacheCommand::Ls => {
+            for entry in cache.list().await? {
+                println!(
+                    "{:>5} ago  {}  {}",
+                    format_age(entry.age()),
+                    entry.key,
+                    entry.title
+                );
+            }
+        }
+        CacheCommand::Prune { older_than } => {
+            let max_age = parse_age(&older_than)
+                .with_context(|| format!("Invalid age: {} (expected e.g. 30d)", older_than))?;
+            let removed = cache.prune(max_age).await?;
+            println!("Removed {} cached transcripts", removed);
+        }
+    }
+    Ok(())
+}
+
+/// Parses ages such as `90s`, `45m`, `12h`, `30d` or `2w`.
+fn parse_age(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let unit_start = value.find(|c: char| !c.is_ascii_digit())?;
+    let number: u64 = value[..unit_start].parse().ok()?;
+    let scale = match &value[unit_start..] {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(number.checked_mul(scale)?))
+}
+
+fn format_age(age: Duration) -> String {
+    match age.as_secs() {
+        s if s < 60 * 60 => format!("{}m", s / 60),
+        s if s < 24 * 60 * 60 => format!("{}h", s / (60 * 60)),
+        s => format!("{}d", s / (24 * 60 * 60)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_args_select_a_clip() {
+        let args = |start: Option<&str>, end: Option<&str>| ClipArgs {
+            start: start.map(str::to_string),
+            end: end.map(str::to_string),
+            chapter: None,
+        };
+        assert!(args(None, None).clip().unwrap().is_none());
+        assert!(args(Some("0"), None).clip().unwrap().is_none());
+        assert!(matches!(
+            args(Some("12:00"), Some("40:00")).clip().unwrap(),
+            Some(Clip::Range(Range {
+                start: 720.0,
+                end: Some(2400.0)
+            }))
+        ));
+        assert!(args(Some("10"), Some("5")).clip().is_err());
+        assert!(args(Some("soon"), None).clip().is_err());
+    }
+
+    #[test]
+    fn parses_ages() {
+        assert_eq!(parse_age("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_age("45m"), Some(Duration::from_secs(45 * 60)));
+        assert_eq!(parse_age(" 12h "), Some(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_age("30d"), Some(Duration::from_secs(30 * 86400)));
+        assert_eq!(parse_age("2w"), Some(Duration::from_secs(14 * 86400)));
+    }
+
+    #[test]
+    fn rejects_invalid_ages() {
+        assert_eq!(parse_age("30"), None);
+        assert_eq!(parse_age("d"), None);
+        assert_eq!(parse_age("1.5h"), None);
+        assert_eq!(parse_age("3 days"), None);
+        assert_eq!(parse_age("-1d"), None);
+        assert_eq!(parse_age("99999999999999999w"), None);
+    }
+
+    #[test]
+    fn formats_ages() {
+        let age = Duration::from_secs;
+        assert_eq!(format_age(age(59)), "0m");
+        assert_eq!(format_age(age(3599)), "59m");
+        assert_eq!(format_age(age(3600)), "1h");
+        assert_eq!(format_age(age(3 * 86400 + 5)), "3d");
+    }
+}
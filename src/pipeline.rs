@@ -0,0 +1,754 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tempfile::TempDir;
+use tokio::{
+    fs::{remove_file, File},
+    sync::Semaphore,
+};
+
+use crate::audio::{convert_audio, download_audio};
+use crate::backend::Transcriber;
+use crate::batch::{Item, Source};
+use crate::cache::{self, Cache};
+use crate::captions::{self, Captions};
+use crate::chunk;
+use crate::clip::Clip;
+use crate::diarize::{self, Diarizer};
+use crate::profile::Encoding;
+use crate::progress::{Observer, Reporter, Stage, Tracker};
+use crate::stream;
+use crate::transcript::{Chapter, Detail, Metadata, Transcript};
+
+/// Concurrency limits for each pipeline stage, shared by all items.
+struct Limits {
+    download: Semaphore,
+    convert: Semaphore,
+    upload: Semaphore,
+}
+
+impl Limits {
+    fn new(jobs: usize, max_uploads: usize) -> Self {
+        Limits {
+            download: Semaphore::new(jobs.max(1)),
+            convert: Semaphore::new(jobs.max(1)),
+            upload: Semaphore::new(max_uploads.max(1)),
+        }
+    }
+}
+
+/// Turns items into transcripts: from the cache, from the video's captions,
+/// or by downloading, converting and transcribing the audio. Items can be
+/// transcribed concurrently; the stage limits are shared between them.
+pub struct Pipeline {
+    transcriber: Arc<dyn Transcriber>,
+    diarizer: Option<Box<dyn Diarizer>>,
+    limits: Limits,
+    /// Timing detail requested from the backend.
+    detail: Detail,
+    encoding: Encoding,
+    captions: Option<Captions>,
+    clip: Option<Clip>,
+    cache: Option<Cache>,
+    refresh: bool,
+    /// Everything besides the audio that changes the transcript, for cache keys.
+    cache_options: String,
+    stream: bool,
+    /// Temporary directory holding all intermediate audio files.
+    workdir: PathBuf,
+    keep_temp: bool,
+    reporter: Arc<Reporter>,
+    /// Removes the working directory with the pipeline if we created it.
+    _workspace: Option<TempDir>,
+}
+
+/// Configures a [`Pipeline`]. Everything but the transcriber is optional.
+pub struct PipelineBuilder {
+    transcriber: Arc<dyn Transcriber>,
+    diarizer: Option<Box<dyn Diarizer>>,
+    jobs: usize,
+    max_uploads: usize,
+    detail: Detail,
+    encoding: Option<Encoding>,
+    captions: Option<Captions>,
+    clip: Option<Clip>,
+    cache: Option<Cache>,
+    refresh: bool,
+    stream: bool,
+    workdir: Option<PathBuf>,
+    keep_temp: bool,
+    reporter: Option<Arc<Reporter>>,
+}
+
+impl PipelineBuilder {
+    /// Labels speakers with `diarizer`. Asks the backend for segments.
+    pub fn diarizer(mut self, diarizer: Box<dyn Diarizer>) -> Self {
+        self.diarizer = Some(diarizer);
+        self
+    }
+
+    /// Number of items downloaded and converted at the same time (2).
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Number of transcription requests in flight at the same time (2).
+    pub fn max_uploads(mut self, max_uploads: usize) -> Self {
+        self.max_uploads = max_uploads;
+        self
+    }
+
+    /// Timing detail to request from the backend (text only).
+    pub fn detail(mut self, detail: Detail) -> Self {
+        self.detail = detail;
+        self
+    }
+
+    /// How to encode audio for upload (the transcriber's default profile).
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Uses the video's own captions instead of transcribing, if there are
+    /// suitable ones.
+    pub fn captions(mut self, captions: Captions) -> Self {
+        self.captions = Some(captions);
+        self
+    }
+
+    /// Only transcribes part of every item.
+    pub fn clip(mut self, clip: Clip) -> Self {
+        self.clip = Some(clip);
+        self
+    }
+
+    /// Reads transcripts from and writes them to `cache`.
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Replaces cached transcripts instead of reading them.
+    pub fn refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Pipes YouTube audio straight into the upload, see [`stream::transcribe`].
+    pub fn stream(mut self, stream: bool) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    /// Keeps intermediate files in `workdir`, which must exist and is left
+    /// in place (a new temporary directory).
+    pub fn workdir(mut self, workdir: PathBuf) -> Self {
+        self.workdir = Some(workdir);
+        self
+    }
+
+    /// Keeps intermediate files around for debugging.
+    pub fn keep_temp(mut self, keep_temp: bool) -> Self {
+        self.keep_temp = keep_temp;
+        self
+    }
+
+    /// Shows progress through `reporter` (quiet).
+    pub fn reporter(mut self, reporter: Arc<Reporter>) -> Self {
+        self.reporter = Some(reporter);
+        self
+    }
+
+    pub fn build(self) -> crate::Result<Pipeline> {
+        let encoding = match self.encoding {
+            Some(encoding) => encoding,
+            None => Encoding::new(self.transcriber.default_profile(), None, None, &[])?,
+        };
+        // Speakers are assigned to segments
+        let detail = if self.diarizer.is_some() {
+            self.detail.max(Detail::Segments)
+        } else {
+            self.detail
+        };
+        let cache_options = format!(
+            "{}|{}|segments={}|words={}|{}|{}|{}|{}",
+            self.transcriber.name(),
+            self.transcriber.model(),
+            detail >= Detail::Segments,
+            detail == Detail::Words,
+            self.transcriber.cache_key(),
+            encoding.cache_key(),
+            Clip::cache_key(self.clip.as_ref()),
+            self.diarizer
+                .as_ref()
+                .map_or_else(|| "diarize=".to_string(), |d| d.cache_key())
+        );
+
+        let (workdir, workspace) = match self.workdir {
+            Some(workdir) => (workdir, None),
+            None => {
+                let workspace = tempfile::Builder::new()
+                    .prefix("yt-dlts-")
+                    .tempdir()
+                    .context("Failed to create temporary directory")?;
+                if self.keep_temp {
+                    (workspace.keep(), None)
+                } else {
+                    (workspace.path().to_path_buf(), Some(workspace))
+                }
+            }
+        };
+
+        Ok(Pipeline {
+            transcriber: self.transcriber,
+            diarizer: self.diarizer,
+            limits: Limits::new(self.jobs, self.max_uploads),
+            detail,
+            encoding,
+            captions: self.captions,
+            clip: self.clip,
+            cache: self.cache,
+            refresh: self.refresh,
+            cache_options,
+            stream: self.stream,
+            workdir,
+            keep_temp: self.keep_temp,
+            reporter: self.reporter.unwrap_or_else(Reporter::quiet),
+            _workspace: workspace,
+        })
+    }
+}
+
+impl Pipeline {
+    pub fn builder(transcriber: Arc<dyn Transcriber>) -> PipelineBuilder {
+        PipelineBuilder {
+            transcriber,
+            diarizer: None,
+            jobs: 2,
+            max_uploads: 2,
+            detail: Detail::Text,
+            encoding: None,
+            captions: None,
+            clip: None,
+            cache: None,
+            refresh: false,
+            stream: false,
+            workdir: None,
+            keep_temp: false,
+            reporter: None,
+        }
+    }
+
+    pub fn transcriber(&self) -> &dyn Transcriber {
+        self.transcriber.as_ref()
+    }
+
+    pub fn reporter(&self) -> &Arc<Reporter> {
+        &self.reporter
+    }
+
+    /// Directory holding the intermediate files.
+    pub fn workdir(&self) -> &Path {
+        &self.workdir
+    }
+
+    /// Transcribes `item`, the `index`th of the run starting at 1, which
+    /// keeps its temporary files apart from those of other items.
+    pub async fn transcribe(&self, item: &Item, index: usize) -> crate::Result<Transcript> {
+        let tracker = self.reporter.item(&item.title, &item.id, index);
+        self.transcribe_tracked(item, index, &tracker).await
+    }
+
+    /// Like [`Pipeline::transcribe`], reporting progress through `tracker`.
+    pub async fn transcribe_tracked(
+        &self,
+        item: &Item,
+        index: usize,
+        tracker: &Tracker,
+    ) -> crate::Result<Transcript> {
+        let transcript = self.fetch_transcript(item, index, tracker).await;
+        tracker.done();
+        let mut transcript = transcript?;
+
+        // Items yt-dlp did not describe still get a source for links and
+        // --with-metadata
+        if transcript.source.is_none() {
+            transcript.source = Some(Metadata {
+                title: item.title.clone(),
+                id: item.id.clone(),
+                url: (item.source != Source::Stdin).then(|| item.url.clone()),
+                backend: self.transcriber.name().to_string(),
+                model: self.transcriber.model().to_string(),
+                ..Default::default()
+            });
+        }
+        Ok(transcript)
+    }
+
+    /// Transcribes an audio file that is already encoded for upload,
+    /// splitting it if needed and labelling speakers if there is a diarizer.
+    pub async fn transcribe_file(
+        &self,
+        file_path: &str,
+        tracker: &Tracker,
+    ) -> crate::Result<Transcript> {
+        // Transcribe audio, and find the speakers at the same time
+        let (mut transcript, turns) =
+            tokio::try_join!(self.transcribe_audio(file_path, tracker), async {
+                match &self.diarizer {
+                    Some(diarizer) => diarizer.diarize(file_path).await.map(Some),
+                    None => Ok(None),
+                }
+                .map_err(anyhow::Error::from)
+            })?;
+        if let Some(turns) = turns {
+            diarize::assign_speakers(&mut transcript, &turns);
+        }
+        Ok(transcript)
+    }
+
+    fn temp_file(&self, name: &str) -> Result<String> {
+        self.workdir
+            .join(name)
+            .to_str()
+            .map(str::to_string)
+            .context("Temporary directory path is not valid UTF-8")
+    }
+
+    async fn cached(&self, key: Option<&String>) -> Option<Transcript> {
+        if self.refresh {
+            return None;
+        }
+        self.cache.as_ref()?.get(key?).await
+    }
+
+    async fn transcribe_audio(&self, file_path: &str, tracker: &Tracker) -> Result<Transcript> {
+        let chunks = if self.transcriber.needs_chunking() {
+            chunk::split_audio(file_path).await?
+        } else {
+            Vec::new()
+        };
+        if chunks.is_empty() {
+            let _permit = self.limits.upload.acquire().await?;
+            tracker.start(Stage::Transcribe);
+            let transcript = self
+                .transcriber
+                .transcribe(file_path, self.detail, Arc::new(tracker.clone()))
+                .await;
+            tracker.finish(Stage::Transcribe);
+            return Ok(transcript?);
+        }
+
+        // Chunks are uploaded concurrently, bounded by the upload limit
+        let count = chunks.len();
+        let parts =
+            futures::future::try_join_all(chunks.iter().enumerate().map(|(i, c)| async move {
+                let _permit = self.limits.upload.acquire().await?;
+                tracker.message(&format!(
+                    "Transcribing chunk {}/{} (starting at {:.0}s)",
+                    i + 1,
+                    count,
+                    c.start
+                ));
+                let part = self
+                    .transcriber
+                    .transcribe(&c.path, self.detail, Arc::new(tracker.clone()))
+                    .await?;
+                anyhow::Ok(part)
+            }));
+        tracker.start(Stage::Transcribe);
+        let parts = parts.await;
+        tracker.finish(Stage::Transcribe);
+        let parts = parts?;
+
+        let mut transcript = Transcript::default();
+        for (i, part) in parts.into_iter().enumerate() {
+            let cutoff = chunks.get(i + 1).map(|next| next.start);
+            transcript.append(part, chunks[i].start, cutoff);
+        }
+
+        if !self.keep_temp {
+            chunk::remove_chunks(&chunks).await?;
+        }
+
+        Ok(transcript)
+    }
+
+    /// Gets the transcript for `item` from the cache, its captions or by
+    /// downloading and transcribing it.
+    async fn fetch_transcript(
+        &self,
+        item: &Item,
+        index: usize,
+        tracker: &Tracker,
+    ) -> Result<Transcript> {
+        let audio_file = self.temp_file(&format!("audio.{}.webm", index))?;
+        let converted_audio = self.temp_file(&format!(
+            "converted.{}.{}",
+            index,
+            self.encoding.extension()
+        ))?;
+
+        let video_key =
+            (item.source == Source::YtDlp).then(|| Cache::video_key(&item.id, &self.cache_options));
+        if let Some(transcript) = self.cached(video_key.as_ref()).await {
+            tracker.message(&format!("Using cached transcript for {}", item.title));
+            return Ok(transcript);
+        }
+
+        let range = match &self.clip {
+            Some(clip) => Some(clip.resolve(item).await?),
+            None => None,
+        };
+
+        // Existing captions make downloading and transcribing unnecessary
+        if let (Some(captions), Source::YtDlp) = (&self.captions, item.source) {
+            let _permit = self.limits.download.acquire().await?;
+            if let Some(mut transcript) =
+                captions::fetch(captions, &item.url, &self.workdir, index, tracker).await?
+            {
+                if let Some(range) = &range {
+                    transcript.restrict(range.start, range.end);
+                }
+                return Ok(transcript);
+            }
+            tracker.message(&format!(
+                "No suitable captions found for {}, transcribing",
+                item.title
+            ));
+        }
+
+        let result = async {
+            let (mut transcript, info, audio_key) = if self.stream && item.source == Source::YtDlp {
+                let info_file = self.temp_file(&format!("info.{}.json", index))?;
+                let _download = self.limits.download.acquire().await?;
+                let _upload = self.limits.upload.acquire().await?;
+                let (transcript, info) = stream::transcribe(
+                    self.transcriber.as_ref(),
+                    &item.url,
+                    &self.encoding,
+                    range.as_ref(),
+                    &info_file,
+                    self.detail,
+                    tracker,
+                )
+                .await?;
+                (transcript, Some(info), None)
+            } else {
+                // Download audio (local files and direct links skip this).
+                // yt-dlp only fetches the requested range; everything else
+                // is cut by ffmpeg
+                let (input, seek, info) = match item.source {
+                    Source::YtDlp => {
+                        let _permit = self.limits.download.acquire().await?;
+                        let info =
+                            download_audio(&item.url, &audio_file, range.as_ref(), tracker).await?;
+                        (audio_file.as_str(), None, Some(info))
+                    }
+                    Source::Direct => (item.url.as_str(), range.as_ref(), None),
+                    Source::Stdin => {
+                        save_stdin(&audio_file).await?;
+                        (audio_file.as_str(), range.as_ref(), None)
+                    }
+                };
+
+                // Convert audio
+                {
+                    let _permit = self.limits.convert.acquire().await?;
+                    convert_audio(input, &converted_audio, &self.encoding, seek, tracker).await?;
+                }
+
+                // The same audio may have been transcribed under another ID or path
+                let audio_key = match self.cache {
+                    Some(_) => Some(Cache::audio_key(
+                        &cache::hash_file(&converted_audio).await?,
+                        &self.cache_options,
+                    )),
+                    None => None,
+                };
+                if let Some(mut transcript) = self.cached(audio_key.as_ref()).await {
+                    tracker.message(&format!("Using cached transcript for {}", item.title));
+                    self.describe(&mut transcript, info.as_ref());
+                    return Ok(transcript);
+                }
+
+                let transcript = self.transcribe_file(&converted_audio, tracker).await?;
+                (transcript, info, audio_key)
+            };
+
+            transcript.audio_filters = self.encoding.filters.clone();
+            self.describe(&mut transcript, info.as_ref());
+            // Keep timestamps relative to the original video, not the clip
+            if let Some(range) = &range {
+                transcript.shift(range.start);
+            }
+
+            if let Some(cache) = &self.cache {
+                for key in video_key.iter().chain(&audio_key) {
+                    if let Err(e) = cache.put(key, &item.title, &transcript).await {
+                        tracker.warning(&format!("Failed to cache transcript: {:#}", e));
+                    }
+                }
+            }
+
+            Ok(transcript)
+        }
+        .await;
+
+        // Free disk space early during long batches; the workspace itself is
+        // removed when the run ends
+        if !self.keep_temp {
+            remove_file(&audio_file).await.ok();
+            remove_file(&converted_audio).await.ok();
+        }
+
+        result
+    }
+
+    /// Attaches the chapters and source details from yt-dlp's info JSON.
+    fn describe(&self, transcript: &mut Transcript, info: Option<&Value>) {
+        if let Some(info) = info {
+            transcript.chapters = Chapter::list(info);
+            transcript.source = Some(Metadata::from_info(
+                info,
+                self.transcriber.name(),
+                self.transcriber.model(),
+            ));
+        }
+    }
+}
+
+async fn save_stdin(output_file: &str) -> Result<()> {
+    let mut file = File::create(output_file)
+        .await
+        .context("Failed to create temporary input file")?;
+    tokio::io::copy(&mut tokio::io::stdin(), &mut file)
+        .await
+        .context("Failed to read media from standard input")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diarize::Turn;
+    use crate::error::{ApiError, Error};
+    use crate::transcript::Segment;
+    use async_trait::async_trait;
+    use reqwest::StatusCode;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Returns a fixed transcript, or fails with `fail`, and counts how
+    /// often it was called and how many calls ran at the same time.
+    #[derive(Default)]
+    struct MockTranscriber {
+        fail: Option<StatusCode>,
+        calls: AtomicUsize,
+        running: AtomicUsize,
+        most_running: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Transcriber for MockTranscriber {
+        fn name(&self) -> &str {
+            "mock"
+        }
+
+        fn model(&self) -> &str {
+            "mock-1"
+        }
+
+        fn needs_chunking(&self) -> bool {
+            false
+        }
+
+        async fn transcribe(
+            &self,
+            _file_path: &str,
+            _detail: Detail,
+            _observer: Arc<dyn Observer>,
+        ) -> crate::Result<Transcript> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_running.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+
+            if let Some(status) = self.fail {
+                let body = r#"{"error": {"message": "Invalid API key"}}"#;
+                return Err(ApiError::from_response(status, body).into());
+            }
+            Ok(Transcript {
+                text: "Hello there. General Kenobi.".to_string(),
+                segments: vec![
+                    segment(0.0, 1.5, "Hello there."),
+                    segment(2.0, 4.0, "General Kenobi."),
+                ],
+                ..Default::default()
+            })
+        }
+    }
+
+    struct MockDiarizer;
+
+    #[async_trait]
+    impl Diarizer for MockDiarizer {
+        fn cache_key(&self) -> String {
+            "diarize=mock".to_string()
+        }
+
+        async fn diarize(&self, _file_path: &str) -> crate::Result<Vec<Turn>> {
+            Ok(vec![turn("A", 0.0, 1.8), turn("B", 1.8, 4.0)])
+        }
+    }
+
+    fn segment(start: f64, end: f64, text: &str) -> Segment {
+        Segment {
+            start,
+            end,
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn turn(speaker: &str, start: f64, end: f64) -> Turn {
+        Turn {
+            speaker: speaker.to_string(),
+            start,
+            end,
+        }
+    }
+
+    fn audio_file(dir: &Path) -> String {
+        let path = dir.join("audio.webm");
+        std::fs::write(&path, b"not really audio").unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn tracker() -> Tracker {
+        Reporter::quiet().item("test", "test", 1)
+    }
+
+    #[tokio::test]
+    async fn transcribe_file_labels_speakers() {
+        let dir = tempfile::tempdir().unwrap();
+        let pipeline = Pipeline::builder(Arc::new(MockTranscriber::default()))
+            .diarizer(Box::new(MockDiarizer))
+            .workdir(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+
+        let transcript = pipeline
+            .transcribe_file(&audio_file(dir.path()), &tracker())
+            .await
+            .unwrap();
+
+        let speakers: Vec<_> = transcript
+            .segments
+            .iter()
+            .map(|s| s.speaker.as_deref())
+            .collect();
+        assert_eq!(speakers, [Some("Speaker 1"), Some("Speaker 2")]);
+    }
+
+    #[tokio::test]
+    async fn diarizer_asks_for_segments() {
+        let pipeline = Pipeline::builder(Arc::new(MockTranscriber::default()))
+            .diarizer(Box::new(MockDiarizer))
+            .build()
+            .unwrap();
+
+        assert_eq!(pipeline.detail, Detail::Segments);
+        assert!(pipeline
+            .cache_options
+            .starts_with("mock|mock-1|segments=true|"));
+        assert!(pipeline.cache_options.ends_with("|diarize=mock"));
+    }
+
+    #[tokio::test]
+    async fn uploads_respect_max_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        let transcriber = Arc::new(MockTranscriber::default());
+        let pipeline = Pipeline::builder(transcriber.clone())
+            .max_uploads(2)
+            .workdir(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let file = audio_file(dir.path());
+        let tracker = tracker();
+
+        let results =
+            futures::future::join_all((0..5).map(|_| pipeline.transcribe_file(&file, &tracker)))
+                .await;
+
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(transcriber.calls.load(Ordering::SeqCst), 5);
+        assert_eq!(transcriber.most_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn api_errors_stay_typed() {
+        let dir = tempfile::tempdir().unwrap();
+        let transcriber = MockTranscriber {
+            fail: Some(StatusCode::UNAUTHORIZED),
+            ..Default::default()
+        };
+        let pipeline = Pipeline::builder(Arc::new(transcriber))
+            .workdir(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+
+        let error = pipeline
+            .transcribe_file(&audio_file(dir.path()), &tracker())
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(error, Error::Api(ApiError::Auth(_))),
+            "{:?}",
+            error
+        );
+        assert_eq!(error.api_error().map(ApiError::exit_code), Some(3));
+    }
+
+    #[tokio::test]
+    async fn cached_videos_are_not_transcribed() {
+        let dir = tempfile::tempdir().unwrap();
+        let transcriber = Arc::new(MockTranscriber::default());
+        let pipeline = Pipeline::builder(transcriber.clone())
+            .cache(Cache::at(dir.path().join("cache")).unwrap())
+            .workdir(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let item = Item {
+            url: "https://www.youtube.com/watch?v=abc".to_string(),
+            id: "abc".to_string(),
+            title: "A talk".to_string(),
+            source: Source::YtDlp,
+        };
+        let cached = Transcript {
+            text: "From the cache.".to_string(),
+            ..Default::default()
+        };
+        let key = Cache::video_key(&item.id, &pipeline.cache_options);
+        pipeline
+            .cache
+            .as_ref()
+            .unwrap()
+            .put(&key, &item.title, &cached)
+            .await
+            .unwrap();
+
+        let transcript = pipeline.transcribe(&item, 1).await.unwrap();
+
+        assert_eq!(transcript.text, "From the cache.");
+        assert_eq!(transcript.source.unwrap().title, "A talk");
+        assert_eq!(transcriber.calls.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,234 @@
+use clap::ValueEnum;
+
+use crate::error::Result;
+
+/// Cleanup applied to the audio before it is encoded.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Preprocessing {
+    /// Normalize loudness (ffmpeg loudnorm).
+    pub normalize: bool,
+    /// Cut silences longer than a second (ffmpeg silenceremove). Timestamps
+    /// then no longer line up with the original audio.
+    pub trim_silence: bool,
+    /// Remove rumble and background noise (ffmpeg highpass and afftdn).
+    pub denoise: bool,
+}
+
+impl Preprocessing {
+    /// The ffmpeg filters for the selected cleanup, in the order they run.
+    ///
+    /// Noise is removed first so it does not count as speech when trimming
+    /// silence, and loudness is normalized last, on the final signal.
+    pub fn filters(&self) -> Vec<&'static str> {
+        let mut filters = Vec::new();
+        if self.denoise {
+            filters.extend(["highpass=f=80", "afftdn=nf=-25"]);
+        }
+        if self.trim_silence {
+            filters.push(
+                "silenceremove=start_periods=1:start_threshold=-50dB:\
+                 stop_periods=-1:stop_duration=1:stop_threshold=-50dB",
+            );
+        }
+        if self.normalize {
+            filters.push("loudnorm=I=-16:TP=-1.5:LRA=11");
+        }
+        filters
+    }
+}
+
+/// Named ffmpeg encoding presets.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    /// Mono 16 kHz Opus at 24 kbps in WebM, small enough for long uploads
+    OpusLow,
+    /// Mono 16 kHz FLAC, lossless
+    #[value(name = "flac-16k")]
+    Flac16k,
+    /// Mono 16 kHz 16-bit PCM WAV, what whisper.cpp expects
+    WavPcm16,
+    /// Mono 22.05 kHz MP3 at 64 kbps
+    Mp3,
+}
+
+impl Profile {
+    fn codec(self) -> &'static str {
+        match self {
+            Profile::OpusLow => "libopus",
+            Profile::Flac16k => "flac",
+            Profile::WavPcm16 => "pcm_s16le",
+            Profile::Mp3 => "libmp3lame",
+        }
+    }
+
+    fn default_bitrate(self) -> Option<&'static str> {
+        match self {
+            Profile::OpusLow => Some("24k"),
+            Profile::Mp3 => Some("64k"),
+            Profile::Flac16k | Profile::WavPcm16 => None,
+        }
+    }
+
+    fn default_sample_rate(self) -> u32 {
+        match self {
+            Profile::Mp3 => 22050,
+            _ => 16000,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Profile::OpusLow => "webm",
+            Profile::Flac16k => "flac",
+            Profile::WavPcm16 => "wav",
+            Profile::Mp3 => "mp3",
+        }
+    }
+}
+
+/// A profile with the command-line overrides and filters applied.
+#[derive(Clone, Debug)]
+pub struct Encoding {
+    pub profile: Profile,
+    pub bitrate: Option<String>,
+    pub sample_rate: u32,
+    /// ffmpeg `-af` filter chain, if any preprocessing was requested.
+    pub filters: Option<String>,
+}
+
+impl Encoding {
+    pub fn new(
+        profile: Profile,
+        bitrate: Option<String>,
+        sample_rate: Option<u32>,
+        filters: &[&str],
+    ) -> Result<Self> {
+        if bitrate.is_some() && profile.default_bitrate().is_none() {
+            return Err(
+                anyhow::anyhow!("--audio-bitrate cannot be used with lossless profiles").into(),
+            );
+        }
+
+        Ok(Encoding {
+            profile,
+            bitrate: bitrate.or_else(|| profile.default_bitrate().map(str::to_string)),
+            sample_rate: sample_rate.unwrap_or_else(|| profile.default_sample_rate()),
+            filters: (!filters.is_empty()).then(|| filters.join(",")),
+        })
+    }
+
+    /// File extension for the converted audio.
+    pub fn extension(&self) -> &'static str {
+        self.profile.extension()
+    }
+
+    /// ffmpeg output options selecting the filters, codec, bitrate and
+    /// sample rate.
+    pub fn ffmpeg_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(filters) = &self.filters {
+            args.extend(["-af".to_string(), filters.clone()]);
+        }
+        args.extend(["-c:a".to_string(), self.profile.codec().to_string()]);
+        if let Some(bitrate) = &self.bitrate {
+            args.extend(["-b:a".to_string(), bitrate.clone()]);
+        }
+        args.extend(["-ar".to_string(), self.sample_rate.to_string()]);
+        args
+    }
+
+    /// Identifies these settings in cache keys.
+    pub fn cache_key(&self) -> String {
+        format!(
+            "profile={:?}|bitrate={}|rate={}|filters={}",
+            self.profile,
+            self.bitrate.as_deref().unwrap_or_default(),
+            self.sample_rate,
+            self.filters.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &[String]) -> Vec<&str> {
+        s.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn profiles_fill_in_their_defaults() {
+        let opus = Encoding::new(Profile::OpusLow, None, None, &[]).unwrap();
+        assert_eq!(
+            args(&opus.ffmpeg_args()),
+            ["-c:a", "libopus", "-b:a", "24k", "-ar", "16000"]
+        );
+        assert_eq!(opus.extension(), "webm");
+
+        let mp3 = Encoding::new(Profile::Mp3, Some("32k".to_string()), Some(8000), &[]).unwrap();
+        assert_eq!(
+            args(&mp3.ffmpeg_args()),
+            ["-c:a", "libmp3lame", "-b:a", "32k", "-ar", "8000"]
+        );
+
+        let wav = Encoding::new(Profile::WavPcm16, None, None, &[]).unwrap();
+        assert_eq!(
+            args(&wav.ffmpeg_args()),
+            ["-c:a", "pcm_s16le", "-ar", "16000"]
+        );
+    }
+
+    #[test]
+    fn lossless_profiles_reject_a_bitrate() {
+        for profile in [Profile::Flac16k, Profile::WavPcm16] {
+            let err = Encoding::new(profile, Some("64k".to_string()), None, &[]).unwrap_err();
+            assert!(err.to_string().contains("--audio-bitrate"));
+        }
+    }
+
+    #[test]
+    fn filters_come_first_and_change_the_cache_key() {
+        let plain = Encoding::new(Profile::Flac16k, None, None, &[]).unwrap();
+        let filtered =
+            Encoding::new(Profile::Flac16k, None, None, &["highpass=f=80", "loudnorm"]).unwrap();
+        assert_eq!(
+            args(&filtered.ffmpeg_args()),
+            [
+                "-af",
+                "highpass=f=80,loudnorm",
+                "-c:a",
+                "flac",
+                "-ar",
+                "16000"
+            ]
+        );
+        assert_eq!(
+            filtered.cache_key(),
+            "profile=Flac16k|bitrate=|rate=16000|filters=highpass=f=80,loudnorm"
+        );
+        assert_ne!(plain.cache_key(), filtered.cache_key());
+    }
+
+    #[test]
+    fn filters_run_in_a_fixed_order() {
+        let all = Preprocessing {
+            normalize: true,
+            trim_silence: true,
+            denoise: true,
+        };
+        let filters = all.filters();
+        let position = |prefix: &str| filters.iter().position(|f| f.starts_with(prefix));
+        assert_eq!(filters.len(), 4);
+        assert!(position("highpass") < position("afftdn"));
+        assert!(position("afftdn") < position("silenceremove"));
+        assert!(position("silenceremove") < position("loudnorm"));
+
+        let normalize = Preprocessing {
+            normalize: true,
+            ..Default::default()
+        };
+        assert_eq!(normalize.filters(), ["loudnorm=I=-16:TP=-1.5:LRA=11"]);
+        assert!(Preprocessing::default().filters().is_empty());
+    }
+}
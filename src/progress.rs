@@ -0,0 +1,737 @@
+use anyhow::Context;
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{IsTerminal, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, BufReader, Lines},
+    process::Child,
+};
+
+use crate::error::{ProcessError, Result};
+
+/// How often the live display is redrawn at most.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often plain log lines repeat the progress of a running stage.
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often `progress` events are emitted per stage at most.
+const EVENT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Marks our lines in yt-dlp's output, see `YT_DLP_ARGS`.
+const YT_DLP_MARKER: &str = "[yt-dlts] ";
+
+/// yt-dlp options printing machine-readable download progress, one line per
+/// update, even when `--dump-json` makes it quiet otherwise.
+pub const YT_DLP_ARGS: [&str; 4] = [
+    "--newline",
+    "--progress",
+    "--progress-template",
+    "download:[yt-dlts] %(progress.downloaded_bytes)s %(progress.total_bytes,progress.total_bytes_estimate)s",
+];
+
+/// Format of the event stream selected with `--events`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventFormat {
+    /// One JSON object per line
+    Json,
+}
+
+/// Receives updates from a transcriber while it works on one file.
+pub trait Observer: Send + Sync {
+    /// `sent` bytes of the audio, out of `total` if known, have been uploaded.
+    fn uploaded(&self, _sent: u64, _total: Option<u64>) {}
+
+    /// Something went wrong but is being retried.
+    fn warning(&self, _message: &str) {}
+}
+
+/// Pipeline stages shown in the progress display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Download,
+    Convert,
+    Transcribe,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Download => "download",
+            Stage::Convert => "convert",
+            Stage::Transcribe => "transcribe",
+        })
+    }
+}
+
+/// How far a stage has come.
+#[derive(Clone, Copy, Debug)]
+pub enum Progress {
+    /// Bytes downloaded or uploaded.
+    Bytes { done: u64, total: Option<u64> },
+    /// Seconds of audio converted.
+    Time { done: f64, total: Option<f64> },
+}
+
+impl Progress {
+    fn to_json(self) -> Value {
+        match self {
+            Progress::Bytes { done, total } => json!({ "bytes": done, "total_bytes": total }),
+            Progress::Time { done, total } => json!({ "seconds": done, "total_seconds": total }),
+        }
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Progress::Bytes {
+                done,
+                total: Some(total),
+            } if total > 0 => write!(
+                f,
+                "{:.0}% of {}",
+                done as f64 / total as f64 * 100.0,
+                megabytes(total)
+            ),
+            Progress::Bytes { done, .. } => f.write_str(&megabytes(done)),
+            Progress::Time {
+                done,
+                total: Some(total),
+            } => write!(f, "{}/{}", clock(done), clock(total)),
+            Progress::Time { done, total: None } => f.write_str(&clock(done)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// A display redrawn in place on a terminal.
+    Live,
+    /// Log lines for pipes and files.
+    Plain,
+    /// `--events json`: nothing but events on stderr.
+    Events,
+    /// Nothing at all, for library users with their own output.
+    Quiet,
+}
+
+/// Shows what every item in the run is doing on stderr: a live display
+/// redrawn in place on a terminal, plain log lines otherwise, or JSON events
+/// for programs driving us. A quiet reporter shows nothing.
+pub struct Reporter {
+    mode: Mode,
+    started: Instant,
+    state: Mutex<State>,
+    /// Events kept for tests instead of being written to stderr.
+    #[cfg(test)]
+    emitted: Option<Mutex<Vec<Value>>>,
+}
+
+#[derive(Default)]
+struct State {
+    items: BTreeMap<usize, Line>,
+    next_id: usize,
+    /// Lines of the live display currently on screen.
+    drawn: usize,
+    last_draw: Option<Instant>,
+}
+
+struct Line {
+    label: String,
+    stages: BTreeMap<Stage, Status>,
+}
+
+struct Status {
+    text: String,
+    started: Instant,
+    last_report: Instant,
+}
+
+impl Reporter {
+    pub fn new(events: Option<EventFormat>) -> Arc<Self> {
+        let mode = if events.is_some() {
+            Mode::Events
+        } else if std::io::stderr().is_terminal() {
+            Mode::Live
+        } else {
+            Mode::Plain
+        };
+        Arc::new(Reporter {
+            mode,
+            started: Instant::now(),
+            state: Mutex::default(),
+            #[cfg(test)]
+            emitted: None,
+        })
+    }
+
+    pub fn quiet() -> Arc<Self> {
+        Arc::new(Reporter {
+            mode: Mode::Quiet,
+            started: Instant::now(),
+            state: Mutex::default(),
+            #[cfg(test)]
+            emitted: None,
+        })
+    }
+
+    /// Adds a line for an item to the display. `item` identifies it in
+    /// events, next to its position `index` in the run.
+    pub fn item(self: &Arc<Self>, label: &str, item: &str, index: usize) -> Tracker {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.items.insert(
+            id,
+            Line {
+                label: label.to_string(),
+                stages: BTreeMap::new(),
+            },
+        );
+        Tracker {
+            reporter: self.clone(),
+            id,
+            item: item.to_string(),
+            index,
+            started: Instant::now(),
+        }
+    }
+
+    /// Prints a message for people above the live display. There are no
+    /// messages among events.
+    pub fn message(&self, message: &str) {
+        if matches!(self.mode, Mode::Events | Mode::Quiet) {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        self.clear(&mut state);
+        eprintln!("{}", message);
+        self.draw(&mut state, true);
+    }
+
+    /// Prints a warning that concerns the whole run.
+    pub fn warning(&self, message: &str) {
+        match self.mode {
+            Mode::Events => self.emit(json!({ "event": "warning", "message": message })),
+            _ => self.message(&format!("Warning: {}", message)),
+        }
+    }
+
+    /// Reports the error that ends the run, with a hint on what to do about
+    /// it if there is one. In events mode this is a `run_failed` event, so
+    /// it is not mistaken for one more failed item.
+    pub fn error(&self, error: &anyhow::Error, hint: Option<&str>) {
+        if self.mode == Mode::Events {
+            self.emit(json!({
+                "event": "run_failed",
+                "message": format!("{:#}", error),
+                "hint": hint,
+            }));
+            return;
+        }
+        self.message(&format!("Error: {:#}", error));
+        if let Some(hint) = hint {
+            self.message(hint);
+        }
+    }
+
+    /// Writes one event line, stamped with the seconds since the run started.
+    fn emit(&self, mut event: Value) {
+        event["time"] = json!(self.started.elapsed().as_secs_f64());
+        #[cfg(test)]
+        if let Some(emitted) = &self.emitted {
+            emitted.lock().unwrap().push(event);
+            return;
+        }
+        let mut stderr = std::io::stderr().lock();
+        writeln!(stderr, "{}", event).ok();
+        stderr.flush().ok();
+    }
+
+    /// Changes the line of item `id`. `change` returns what to log about
+    /// it, if anything: a plain log line and the matching event, which is
+    /// handed back in events mode.
+    fn update(
+        &self,
+        id: usize,
+        change: impl FnOnce(&mut Line, Mode) -> Option<(String, Value)>,
+    ) -> Option<Value> {
+        let mut state = self.state.lock().unwrap();
+        let line = state.items.get_mut(&id)?;
+        match (change(line, self.mode), self.mode) {
+            (Some((_, event)), Mode::Events) => return Some(event),
+            (Some((log, _)), Mode::Plain) => eprintln!("{}", log),
+            _ => self.draw(&mut state, false),
+        }
+        None
+    }
+
+    fn remove(&self, id: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.items.remove(&id);
+        self.draw(&mut state, true);
+    }
+
+    fn clear(&self, state: &mut State) {
+        if self.mode == Mode::Live && state.drawn > 0 {
+            eprint!("\x1b[{}A\x1b[J", state.drawn);
+            state.drawn = 0;
+        }
+    }
+
+    fn draw(&self, state: &mut State, force: bool) {
+        if self.mode != Mode::Live {
+            return;
+        }
+        let now = Instant::now();
+        if !force
+            && state
+                .last_draw
+                .is_some_and(|last| now - last < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        state.last_draw = Some(now);
+
+        let mut out = String::new();
+        if state.drawn > 0 {
+            out.push_str(&format!("\x1b[{}A", state.drawn));
+        }
+        for line in state.items.values() {
+            out.push_str(&format!("\r\x1b[2K{}\n", line));
+        }
+        out.push_str("\x1b[J");
+        state.drawn = state.items.len();
+
+        let mut stderr = std::io::stderr().lock();
+        stderr.write_all(out.as_bytes()).ok();
+        stderr.flush().ok();
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let label: String = self.label.chars().take(40).collect();
+        write!(f, "{:<40}", label)?;
+        for (stage, status) in &self.stages {
+            write!(f, "  {} {}", stage, status.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reports the progress of one item.
+#[derive(Clone)]
+pub struct Tracker {
+    reporter: Arc<Reporter>,
+    id: usize,
+    item: String,
+    index: usize,
+    started: Instant,
+}
+
+impl Tracker {
+    pub fn start(&self, stage: Stage) {
+        self.update(|line, _| {
+            let now = Instant::now();
+            line.stages.insert(
+                stage,
+                Status {
+                    text: "...".to_string(),
+                    started: now,
+                    last_report: now,
+                },
+            );
+            Some((
+                format!("{}: {} started", line.label, stage),
+                json!({ "event": "stage_started", "stage": stage.to_string() }),
+            ))
+        });
+    }
+
+    pub fn progress(&self, stage: Stage, progress: Progress) {
+        self.update(|line, mode| {
+            let label = &line.label;
+            let status = line.stages.get_mut(&stage)?;
+            status.text = progress.to_string();
+            let interval = match mode {
+                Mode::Events => EVENT_INTERVAL,
+                _ => LOG_INTERVAL,
+            };
+            let now = Instant::now();
+            if now - status.last_report < interval {
+                return None;
+            }
+            status.last_report = now;
+            let mut event = progress.to_json();
+            event["event"] = json!("progress");
+            event["stage"] = json!(stage.to_string());
+            Some((format!("{}: {} {}", label, stage, status.text), event))
+        });
+    }
+
+    pub fn finish(&self, stage: Stage) {
+        self.update(|line, _| {
+            let status = line.stages.remove(&stage)?;
+            let elapsed = status.started.elapsed().as_secs_f64();
+            Some((
+                format!("{}: {} finished in {:.1}s", line.label, stage, elapsed),
+                json!({
+                    "event": "stage_finished",
+                    "stage": stage.to_string(),
+                    "elapsed": elapsed,
+                }),
+            ))
+        });
+    }
+
+    /// Reports the files the item's transcript was saved to, if any.
+    pub fn result(&self, title: &str, url: &str, outputs: &[String]) {
+        self.event(json!({
+            "event": "result",
+            "title": title,
+            "url": url,
+            "outputs": outputs,
+            "elapsed": self.started.elapsed().as_secs_f64(),
+        }));
+    }
+
+    /// Reports that the item could not be transcribed.
+    pub fn error(&self, error: &anyhow::Error) {
+        self.event(json!({
+            "event": "error",
+            "message": format!("{:#}", error),
+            "elapsed": self.started.elapsed().as_secs_f64(),
+        }));
+    }
+
+    /// Emits an event about the item; only events mode shows these.
+    fn event(&self, mut event: Value) {
+        if self.reporter.mode == Mode::Events {
+            event["item"] = json!(self.item);
+            event["index"] = json!(self.index);
+            self.reporter.emit(event);
+        }
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Line, Mode) -> Option<(String, Value)>) {
+        if let Some(event) = self.reporter.update(self.id, change) {
+            self.event(event);
+        }
+    }
+
+    /// Handles a line of yt-dlp output, returning whether it was progress.
+    pub fn yt_dlp_line(&self, line: &str) -> bool {
+        let Some(progress) = line.strip_prefix(YT_DLP_MARKER) else {
+            return false;
+        };
+        // Fragmented downloads report fractional estimates
+        let mut numbers = progress
+            .split_whitespace()
+            .map(|n| n.parse::<f64>().ok().map(|n| n as u64));
+        if let Some(done) = numbers.next().flatten() {
+            let total = numbers.next().flatten();
+            self.progress(Stage::Download, Progress::Bytes { done, total });
+        }
+        true
+    }
+
+    /// Handles a line of ffmpeg output with `-progress`, returning whether
+    /// it was progress. `duration` is the length of the output if known, or
+    /// filled in from ffmpeg's description of the input.
+    pub fn ffmpeg_line(&self, line: &str, duration: &mut Option<f64>) -> bool {
+        if let Some(time) = line.strip_prefix("out_time_us=") {
+            if let Ok(micros) = time.parse::<f64>() {
+                let progress = Progress::Time {
+                    done: micros / 1_000_000.0,
+                    total: *duration,
+                };
+                self.progress(Stage::Convert, progress);
+            }
+            return true;
+        }
+        if duration.is_none() {
+            *duration = parse_ffmpeg_duration(line);
+        }
+        // Everything else -progress writes is key=value without spaces
+        line.contains('=') && !line.contains(' ')
+    }
+
+    /// Prints a message above the live display.
+    pub fn message(&self, message: &str) {
+        self.reporter.message(message);
+    }
+
+    /// Removes the item from the display.
+    pub fn done(&self) {
+        self.reporter.remove(self.id);
+    }
+}
+
+impl Observer for Tracker {
+    fn uploaded(&self, sent: u64, total: Option<u64>) {
+        let progress = Progress::Bytes { done: sent, total };
+        self.progress(Stage::Transcribe, progress);
+    }
+
+    fn warning(&self, message: &str) {
+        if self.reporter.mode == Mode::Events {
+            self.event(json!({ "event": "warning", "message": message }));
+        } else {
+            self.message(message);
+        }
+    }
+}
+
+/// Waits for `child`, handing every line it writes to its piped stdout and
+/// stderr to `on_line` as it arrives. Lines `on_line` does not take are
+/// kept: stdout is returned, and stderr is the error if `program` fails.
+pub async fn watch(
+    mut child: Child,
+    program: &str,
+    mut on_line: impl FnMut(&str) -> bool,
+) -> Result<String> {
+    let mut stdout = child.stdout.take().map(|out| BufReader::new(out).lines());
+    let mut stderr = child.stderr.take().map(|err| BufReader::new(err).lines());
+    let (mut out, mut err) = (String::new(), String::new());
+
+    while stdout.is_some() || stderr.is_some() {
+        tokio::select! {
+            line = next_line(&mut stdout) => match line.context("Failed to read program output")? {
+                Some(line) if !on_line(&line) => out.push_str(&format!("{}\n", line)),
+                Some(_) => {}
+                None => stdout = None,
+            },
+            line = next_line(&mut stderr) => match line.context("Failed to read program output")? {
+                Some(line) if !on_line(&line) => err.push_str(&format!("{}\n", line)),
+                Some(_) => {}
+                None => stderr = None,
+            },
+        }
+    }
+
+    let status = child
+        .wait()
+        .await
+        .with_context(|| format!("Failed to wait for {}", program))?;
+    if !status.success() {
+        return Err(ProcessError::new(program, err).into());
+    }
+    Ok(out)
+}
+
+/// The next line of a stream, or never once it has been closed.
+async fn next_line<R: AsyncBufRead + Unpin>(
+    lines: &mut Option<Lines<R>>,
+) -> std::io::Result<Option<String>> {
+    match lines {
+        Some(lines) => lines.next_line().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Parses the input length from ffmpeg's `  Duration: 00:01:02.03, ...`.
+fn parse_ffmpeg_duration(line: &str) -> Option<f64> {
+    let rest = line.trim_start().strip_prefix("Duration: ")?;
+    let time = rest.split(',').next()?;
+    time.split(':').try_fold(0.0, |total, part| {
+        Some(total * 60.0 + part.parse::<f64>().ok()?)
+    })
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+}
+
+fn clock(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording() -> Arc<Reporter> {
+        Arc::new(Reporter {
+            mode: Mode::Events,
+            started: Instant::now(),
+            state: Mutex::default(),
+            emitted: Some(Mutex::default()),
+        })
+    }
+
+    /// The events emitted so far, without their varying timings.
+    fn emitted(reporter: &Reporter) -> Vec<Value> {
+        let mut events = reporter.emitted.as_ref().unwrap().lock().unwrap().clone();
+        for event in &mut events {
+            let event = event.as_object_mut().unwrap();
+            assert!(event.remove("time").unwrap().is_f64());
+            if let Some(elapsed) = event.remove("elapsed") {
+                assert!(elapsed.is_f64());
+            }
+        }
+        events
+    }
+
+    /// What the display shows for `stage` of the tracker's item.
+    fn status(tracker: &Tracker, stage: Stage) -> String {
+        let state = tracker.reporter.state.lock().unwrap();
+        state.items[&tracker.id].stages[&stage].text.clone()
+    }
+
+    #[test]
+    fn yt_dlp_progress_lines_are_taken() {
+        let tracker = Reporter::quiet().item("Talk", "https://youtu.be/abc", 1);
+        tracker.start(Stage::Download);
+
+        assert!(tracker.yt_dlp_line("[yt-dlts] 500000 2000000"));
+        assert_eq!(status(&tracker, Stage::Download), "25% of 2.0 MB");
+        // Fragmented downloads estimate the total
+        assert!(tracker.yt_dlp_line("[yt-dlts] 1500000 2000000.5"));
+        assert_eq!(status(&tracker, Stage::Download), "75% of 2.0 MB");
+        assert!(tracker.yt_dlp_line("[yt-dlts] 3000000 NA"));
+        assert_eq!(status(&tracker, Stage::Download), "3.0 MB");
+
+        assert!(!tracker.yt_dlp_line("ERROR: Video unavailable"));
+        assert!(!tracker.yt_dlp_line("{\"id\": \"abc\"}"));
+    }
+
+    #[test]
+    fn ffmpeg_progress_lines_are_taken() {
+        let tracker = Reporter::quiet().item("Talk", "talk.mp3", 1);
+        tracker.start(Stage::Convert);
+        let mut duration = None;
+
+        assert!(!tracker.ffmpeg_line(
+            "  Duration: 01:02:03.50, start: 0.000000, bitrate: 128 kb/s",
+            &mut duration
+        ));
+        assert_eq!(duration, Some(3723.5));
+        assert!(tracker.ffmpeg_line("out_time_us=61000000", &mut duration));
+        assert_eq!(status(&tracker, Stage::Convert), "0:01:01/1:02:03");
+        assert!(tracker.ffmpeg_line("progress=continue", &mut duration));
+        assert!(!tracker.ffmpeg_line("[mp3 @ 0x55] Estimating duration", &mut duration));
+
+        // A known output length is not replaced by the input's
+        let mut clip = Some(30.0);
+        tracker.ffmpeg_line("  Duration: 01:02:03.50, start: 0.0", &mut clip);
+        assert_eq!(clip, Some(30.0));
+    }
+
+    #[test]
+    fn parses_ffmpeg_durations() {
+        assert_eq!(
+            parse_ffmpeg_duration("  Duration: 00:01:02.03, start: 0.000000"),
+            Some(62.03)
+        );
+        assert_eq!(
+            parse_ffmpeg_duration("Duration: 10:00:00.00"),
+            Some(36000.0)
+        );
+        assert_eq!(parse_ffmpeg_duration("  Duration: N/A, bitrate: N/A"), None);
+        assert_eq!(parse_ffmpeg_duration("Stream #0:0: Audio: opus"), None);
+    }
+
+    #[test]
+    fn item_events_carry_the_item_and_index() {
+        let reporter = recording();
+        let tracker = reporter.item("Talk", "https://youtu.be/abc", 2);
+        tracker.start(Stage::Download);
+        tracker.finish(Stage::Download);
+        Observer::warning(&tracker, "Retrying in 1.0s (1/5)");
+        tracker.result("Talk", "https://youtu.be/abc", &["talk.srt".to_string()]);
+        tracker.error(&anyhow::anyhow!("Failed to read audio file"));
+
+        let item = |mut event: Value| {
+            event["item"] = json!("https://youtu.be/abc");
+            event["index"] = json!(2);
+            event
+        };
+        assert_eq!(
+            emitted(&reporter),
+            [
+                item(json!({ "event": "stage_started", "stage": "download" })),
+                item(json!({ "event": "stage_finished", "stage": "download" })),
+                item(json!({ "event": "warning", "message": "Retrying in 1.0s (1/5)" })),
+                item(json!({
+                    "event": "result",
+                    "title": "Talk",
+                    "url": "https://youtu.be/abc",
+                    "outputs": ["talk.srt"],
+                })),
+                item(json!({ "event": "error", "message": "Failed to read audio file" })),
+            ]
+        );
+    }
+
+    #[test]
+    fn progress_events_are_throttled() {
+        let reporter = recording();
+        let tracker = reporter.item("Talk", "talk.mp3", 1);
+        tracker.start(Stage::Transcribe);
+        tracker.uploaded(100, Some(1000));
+        std::thread::sleep(EVENT_INTERVAL);
+        tracker.uploaded(500, Some(1000));
+        tracker.uploaded(600, Some(1000));
+
+        let events = emitted(&reporter);
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[1],
+            json!({
+                "event": "progress",
+                "stage": "transcribe",
+                "bytes": 500,
+                "total_bytes": 1000,
+                "item": "talk.mp3",
+                "index": 1,
+            })
+        );
+        assert_eq!(
+            Progress::Time {
+                done: 1.5,
+                total: None
+            }
+            .to_json(),
+            json!({ "seconds": 1.5, "total_seconds": null })
+        );
+    }
+
+    #[test]
+    fn run_events_have_no_item() {
+        let reporter = recording();
+        reporter.warning("Could not read the URL list");
+        reporter.error(
+            &anyhow::anyhow!("GROQ_API_KEY not set"),
+            Some("Set GROQ_API_KEY"),
+        );
+        reporter.error(&anyhow::anyhow!("No videos found"), None);
+        reporter.message("Not an event");
+
+        assert_eq!(
+            emitted(&reporter),
+            [
+                json!({ "event": "warning", "message": "Could not read the URL list" }),
+                json!({
+                    "event": "run_failed",
+                    "message": "GROQ_API_KEY not set",
+                    "hint": "Set GROQ_API_KEY",
+                }),
+                json!({ "event": "run_failed", "message": "No videos found", "hint": null }),
+            ]
+        );
+    }
+}
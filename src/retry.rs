@@ -0,0 +1,155 @@
+use reqwest::{header::HeaderMap, StatusCode};
+use std::time::Duration;
+
+/// Upper bound for a single wait, whatever the server asks for.
+const MAX_DELAY: Duration = Duration::from_secs(120);
+
+/// Whether a request that failed with `status` is worth sending again.
+pub fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Exponential backoff: 1s, 2s, 4s, ... capped at [`MAX_DELAY`].
+pub fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1u64 << attempt.min(10)).min(MAX_DELAY)
+}
+
+/// How long to wait before retrying, preferring what the server told us.
+///
+/// `Retry-After` wins if present. Otherwise, for 429s, we wait until Groq's
+/// `x-ratelimit-reset-*` windows have reset. Everything else falls back to
+/// [`backoff`].
+pub fn delay(status: StatusCode, headers: &HeaderMap, attempt: u32) -> Duration {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(seconds) = header("retry-after").and_then(|v| v.trim().parse::<f64>().ok()) {
+        return capped(seconds);
+    }
+
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let reset = ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+            .into_iter()
+            .filter_map(|name| header(name).and_then(parse_reset))
+            .max();
+        if let Some(reset) = reset {
+            return reset.min(MAX_DELAY);
+        }
+    }
+
+    backoff(attempt)
+}
+
+/// Parses Groq's reset durations such as `7.66s`, `2m59.56s` or `120ms`.
+fn parse_reset(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = &rest[number_len..];
+
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let scale = match &rest[..unit_len] {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 0.001,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+
+        total += number * scale;
+    }
+
+    // Only digits reach the parser, but enough of them still overflow
+    Some(Duration::try_from_secs_f64(total).unwrap_or(Duration::MAX))
+}
+
+/// Converts `seconds` from `Retry-After` into a wait of at most
+/// [`MAX_DELAY`], even for values such as `inf` or `NaN`.
+fn capped(seconds: f64) -> Duration {
+    Duration::try_from_secs_f64(seconds.max(0.0))
+        .unwrap_or(MAX_DELAY)
+        .min(MAX_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn parses_groq_reset_durations() {
+        assert_eq!(parse_reset("7.66s"), Some(Duration::from_secs_f64(7.66)));
+        assert_eq!(
+            parse_reset("2m59.56s"),
+            Some(Duration::from_secs_f64(179.56))
+        );
+        assert_eq!(parse_reset("120ms"), Some(Duration::from_millis(120)));
+        assert_eq!(parse_reset("1h2m"), Some(Duration::from_secs(3720)));
+        assert_eq!(parse_reset("soon"), None);
+        assert_eq!(parse_reset("5"), None);
+    }
+
+    #[test]
+    fn retry_after_wins() {
+        let headers = headers(&[("retry-after", "3"), ("x-ratelimit-reset-requests", "50s")]);
+        assert_eq!(
+            delay(StatusCode::TOO_MANY_REQUESTS, &headers, 0),
+            Duration::from_secs(3)
+        );
+    }
+
+    #[test]
+    fn rate_limits_wait_for_the_latest_reset() {
+        let headers = headers(&[
+            ("x-ratelimit-reset-requests", "2.5s"),
+            ("x-ratelimit-reset-tokens", "1m0s"),
+        ]);
+        assert_eq!(
+            delay(StatusCode::TOO_MANY_REQUESTS, &headers, 0),
+            Duration::from_secs(60)
+        );
+        // Reset headers only matter for rate limits
+        assert_eq!(
+            delay(StatusCode::BAD_GATEWAY, &headers, 2),
+            Duration::from_secs(4)
+        );
+    }
+
+    #[test]
+    fn delays_are_capped() {
+        for retry_after in ["3600", "inf", "1e400"] {
+            let headers = headers(&[("retry-after", retry_after)]);
+            assert_eq!(
+                delay(StatusCode::SERVICE_UNAVAILABLE, &headers, 0),
+                MAX_DELAY
+            );
+        }
+        let headers = headers(&[("x-ratelimit-reset-requests", "99999999999999999999h")]);
+        assert_eq!(delay(StatusCode::TOO_MANY_REQUESTS, &headers, 0), MAX_DELAY);
+        assert_eq!(backoff(0), Duration::from_secs(1));
+        assert_eq!(backoff(3), Duration::from_secs(8));
+        assert_eq!(backoff(40), MAX_DELAY);
+    }
+
+    #[test]
+    fn only_rate_limits_and_server_errors_are_retried() {
+        assert!(is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable(StatusCode::UNAUTHORIZED));
+        assert!(!is_retryable(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::Context;
+use serde_json::Value;
+use std::{process::Stdio, sync::Arc};
+use tokio::process::Command;
+
+use crate::backend::Transcriber;
+use crate::clip::Range;
+use crate::error::{Error, Result};
+use crate::profile::Encoding;
+use crate::progress::{self, Stage, Tracker};
+use crate::transcript::{Detail, Transcript};
+
+/// Transcribes `url` without writing the audio to disk: yt-dlp's output is
+/// piped straight into ffmpeg, and ffmpeg's output straight into the upload
+/// body. yt-dlp writes the info JSON to `info_file` on the side, since its
+/// standard output carries the audio.
+///
+/// The encoded size is not known up front, so the audio cannot be split into
+/// chunks and a failed upload cannot be retried.
+pub async fn transcribe(
+    transcriber: &dyn Transcriber,
+    url: &str,
+    encoding: &Encoding,
+    range: Option<&Range>,
+    info_file: &str,
+    detail: Detail,
+    tracker: &Tracker,
+) -> Result<(Transcript, Value)> {
+    let mut download = Command::new("yt-dlp")
+        .args(["-f", "bestaudio", "-q"])
+        .args(progress::YT_DLP_ARGS)
+        .args(["--print-to-file", "video:%()j", info_file])
+        .args(["-o", "-", url])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to execute yt-dlp")?;
+    let audio: Stdio = download
+        .stdout
+        .take()
+        .context("yt-dlp has no standard output")?
+        .try_into()
+        .context("Failed to connect yt-dlp to ffmpeg")?;
+
+    // A pipe cannot be seeked, so a range is cut on the output side
+    let mut convert = Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-progress", "pipe:2", "-nostats"])
+        .args(["-i", "pipe:0"])
+        .args(range.map(Range::ffmpeg_args).unwrap_or_default())
+        .args(encoding.ffmpeg_args())
+        .args([
+            "-ac",
+            "1",
+            "-map",
+            "0:a:",
+            "-vn",
+            "-f",
+            encoding.extension(),
+        ])
+        .arg("pipe:1")
+        .stdin(audio)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to execute ffmpeg")?;
+    let encoded = convert
+        .stdout
+        .take()
+        .context("ffmpeg has no standard output")?;
+
+    let file_name = format!("audio.{}", encoding.extension());
+    let mut duration = range.and_then(Range::duration);
+    for stage in [Stage::Download, Stage::Convert, Stage::Transcribe] {
+        tracker.start(stage);
+    }
+    let (transcript, download, convert) = tokio::join!(
+        transcriber.transcribe_stream(
+            Box::new(encoded),
+            &file_name,
+            detail,
+            Arc::new(tracker.clone())
+        ),
+        async {
+            let result =
+                progress::watch(download, "yt-dlp", |line| tracker.yt_dlp_line(line)).await;
+            tracker.finish(Stage::Download);
+            result
+        },
+        async {
+            let result = progress::watch(convert, "ffmpeg", |line| {
+                tracker.ffmpeg_line(line, &mut duration)
+            })
+            .await;
+            tracker.finish(Stage::Convert);
+            result
+        },
+    );
+    tracker.finish(Stage::Transcribe);
+
+    let download = if stopped_reading(&download, &convert, range) {
+        Ok(String::new())
+    } else {
+        download
+    };
+    let processes = download.and(convert);
+    let transcript = match transcript {
+        // A rejected upload closes the pipe and takes ffmpeg down with it,
+        // while a failed download or conversion explains a broken upload
+        Err(e) if e.api_error().is_none() => {
+            processes?;
+            return Err(e);
+        }
+        transcript => transcript?,
+    };
+    processes?;
+
+    let info = tokio::fs::read(info_file)
+        .await
+        .context("Failed to read yt-dlp info JSON")?;
+    let info = serde_json::from_slice(&info).context("Failed to parse yt-dlp info JSON")?;
+    Ok((transcript, info))
+}
+
+/// Whether yt-dlp only failed because ffmpeg stopped reading. ffmpeg does
+/// that once it has cut out a range with an end, and yt-dlp then cannot write
+/// the rest of the video into the closed pipe.
+fn stopped_reading(
+    download: &Result<String>,
+    convert: &Result<String>,
+    range: Option<&Range>,
+) -> bool {
+    let broken_pipe = match download {
+        Err(Error::Process(e)) => {
+            e.message.contains("Broken pipe") || e.message.contains("unable to write data")
+        }
+        _ => false,
+    };
+    broken_pipe && convert.is_ok() && range.is_some_and(|range| range.end.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ProcessError;
+
+    fn failed(message: &str) -> Result<String> {
+        Err(ProcessError::new("yt-dlp", message).into())
+    }
+
+    #[test]
+    fn clips_may_close_the_pipe_early() {
+        let closed = failed("ERROR: unable to write data: [Errno 32] Broken pipe\n");
+        let clip = Range {
+            start: 60.0,
+            end: Some(90.0),
+        };
+        let tail = Range {
+            start: 60.0,
+            end: None,
+        };
+        assert!(stopped_reading(&closed, &Ok(String::new()), Some(&clip)));
+
+        // Without an end, ffmpeg reads everything yt-dlp has
+        assert!(!stopped_reading(&closed, &Ok(String::new()), Some(&tail)));
+        assert!(!stopped_reading(&closed, &Ok(String::new()), None));
+        // A broken conversion also breaks the pipe
+        let convert = Err(ProcessError::new("ffmpeg", "Invalid data").into());
+        assert!(!stopped_reading(&closed, &convert, Some(&clip)));
+        // Other download failures are still errors
+        let unavailable = failed("ERROR: Video unavailable\n");
+        assert!(!stopped_reading(
+            &unavailable,
+            &Ok(String::new()),
+            Some(&clip)
+        ));
+    }
+}
@@ -0,0 +1,878 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::chunk;
+
+/// Output file format selected with `--format`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Plain text
+    Txt,
+    /// The full transcript with segment and word timings as JSON
+    Json,
+    /// SubRip subtitles
+    Srt,
+    /// WebVTT subtitles
+    Vtt,
+    /// Markdown with timestamped paragraphs
+    Md,
+    /// A self-contained, searchable HTML page with timestamped paragraphs
+    Html,
+}
+
+impl Format {
+    /// Timing detail this format needs from the API.
+    pub fn detail(self) -> Detail {
+        match self {
+            Format::Txt => Detail::Text,
+            Format::Srt | Format::Vtt | Format::Md | Format::Html => Detail::Segments,
+            Format::Json => Detail::Words,
+        }
+    }
+
+    /// File extension used for output files in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Txt => "txt",
+            Format::Json => "json",
+            Format::Srt => "srt",
+            Format::Vtt => "vtt",
+            Format::Md => "md",
+            Format::Html => "html",
+        }
+    }
+}
+
+/// Settings for `Transcript::render`.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderOptions {
+    pub format: Format,
+    /// Group text, Markdown and HTML output under chapter headings.
+    pub chapters: bool,
+    /// Include the source metadata (`--with-metadata`).
+    pub metadata: bool,
+    /// Pause in seconds that starts a new paragraph in Markdown and HTML.
+    pub paragraph_gap: f64,
+}
+
+/// How much timing information to request from the backend. Each level
+/// includes the ones before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Detail {
+    /// Just the text.
+    Text,
+    /// Segment timings (`verbose_json`).
+    Segments,
+    /// Segment and word timings.
+    Words,
+}
+
+/// A timed piece of the transcript, in seconds from the start of the audio.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    /// Speaker label from `--diarize`, e.g. `Speaker 1`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<String>,
+    /// Any other fields the API returned (`id`, `avg_logprob`, ...), kept so
+    /// JSON output is lossless.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A single word with its timing, from `timestamp_granularities[]=word`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Word {
+    pub word: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// A chapter of the source video, from yt-dlp's info JSON.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Chapter {
+    pub title: String,
+    #[serde(alias = "start_time")]
+    pub start: f64,
+    #[serde(alias = "end_time")]
+    pub end: f64,
+}
+
+impl Chapter {
+    /// The chapters listed in yt-dlp's info JSON, if any.
+    pub fn list(info: &Value) -> Vec<Chapter> {
+        serde_json::from_value(info["chapters"].clone()).unwrap_or_default()
+    }
+}
+
+/// Where a transcript came from, for `--with-metadata`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Metadata {
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploader: Option<String>,
+    /// Upload date as `YYYY-MM-DD`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_date: Option<String>,
+    /// Length of the whole video in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub backend: String,
+    pub model: String,
+}
+
+impl Metadata {
+    /// Reads the video details from yt-dlp's info JSON.
+    pub fn from_info(info: &Value, backend: &str, model: &str) -> Self {
+        let string = |key: &str| info[key].as_str().map(str::to_string);
+        Metadata {
+            title: string("title").unwrap_or_default(),
+            uploader: string("uploader").or_else(|| string("channel")),
+            // yt-dlp reports dates as YYYYMMDD
+            upload_date: string("upload_date").map(|date| match date.len() {
+                8 => format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]),
+                _ => date,
+            }),
+            duration: info["duration"].as_f64(),
+            id: string("id").unwrap_or_default(),
+            url: string("webpage_url"),
+            backend: backend.to_string(),
+            model: model.to_string(),
+        }
+    }
+
+    /// Link to `seconds` into the video, for YouTube videos.
+    pub fn link(&self, seconds: f64) -> Option<String> {
+        let url = self.url.as_deref()?;
+        (url.contains("youtube.com/") || url.contains("youtu.be/"))
+            .then(|| format!("https://youtu.be/{}?t={}", self.id, seconds as u64))
+    }
+
+    /// `Key: value` lines for the text header, WebVTT note and HTML page.
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("Title: {}", self.title)];
+        if let Some(uploader) = &self.uploader {
+            lines.push(format!("Uploader: {}", uploader));
+        }
+        if let Some(upload_date) = &self.upload_date {
+            lines.push(format!("Upload date: {}", upload_date));
+        }
+        if let Some(duration) = self.duration {
+            lines.push(format!("Duration: {}", clock(duration)));
+        }
+        lines.push(format!("Video ID: {}", self.id));
+        if let Some(url) = &self.url {
+            lines.push(format!("URL: {}", url));
+        }
+        lines.push(format!(
+            "Transcribed with: {} ({})",
+            self.backend, self.model
+        ));
+        lines
+    }
+}
+
+/// A transcription result, as returned by the `json` and `verbose_json`
+/// response formats.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Transcript {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Length of the audio in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub segments: Vec<Segment>,
+    #[serde(default)]
+    pub words: Vec<Word>,
+    /// ffmpeg filter chain the audio went through before transcription,
+    /// recorded so results can be reproduced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_filters: Option<String>,
+    /// Chapters of the source video, with times relative to the full video.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chapters: Vec<Chapter>,
+    /// The video this transcript belongs to, shown with `--with-metadata`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Metadata>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Transcript {
+    /// Appends the transcript of a chunk that starts `offset` seconds into the
+    /// audio. Segments and words starting at or after `cutoff` are dropped,
+    /// since the next chunk covers them again. Segments and words of `part`
+    /// that lie mostly before the end of what is already here are dropped
+    /// too, as they repeat a segment that ran past the previous cutoff.
+    pub fn append(&mut self, mut part: Transcript, offset: f64, cutoff: Option<f64>) {
+        let keep = |start: f64| cutoff.is_none_or(|cutoff| start < cutoff);
+        let segments_end = self.segments.last().map_or(f64::MIN, |s| s.end);
+        let words_end = self.words.last().map_or(f64::MIN, |w| w.end);
+        part.shift(offset);
+
+        self.text = chunk::merge_overlap(&self.text, &part.text);
+        if self.language.is_none() {
+            self.language = part.language;
+        }
+        if let Some(duration) = part.duration {
+            self.duration = Some(offset + duration);
+        }
+        self.segments.extend(
+            part.segments
+                .into_iter()
+                .filter(|s| keep(s.start) && (s.start + s.end) / 2.0 >= segments_end),
+        );
+        self.words.extend(
+            part.words
+                .into_iter()
+                .filter(|w| keep(w.start) && (w.start + w.end) / 2.0 >= words_end),
+        );
+        for (key, value) in part.extra {
+            self.extra.entry(key).or_insert(value);
+        }
+    }
+
+    /// Moves all timings `offset` seconds later, e.g. to make a clip's
+    /// timestamps relative to the full video. The duration stays the length
+    /// of the transcribed audio.
+    pub fn shift(&mut self, offset: f64) {
+        for s in &mut self.segments {
+            s.start += offset;
+            s.end += offset;
+        }
+        for w in &mut self.words {
+            w.start += offset;
+            w.end += offset;
+        }
+    }
+
+    /// Keeps only the segments and words starting within `start..end` and
+    /// rebuilds the text from the remaining segments.
+    pub fn restrict(&mut self, start: f64, end: Option<f64>) {
+        let within = |s: f64| s >= start && end.is_none_or(|end| s < end);
+        self.segments.retain(|s| within(s.start));
+        self.words.retain(|w| within(w.start));
+        self.text = self
+            .segments
+            .iter()
+            .map(|s| s.text.trim())
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+
+    /// The part of the transcript belonging to `chapter`.
+    pub fn chapter(&self, chapter: &Chapter) -> Transcript {
+        let mut part = self.clone();
+        part.restrict(chapter.start, Some(chapter.end));
+        part.chapters = vec![chapter.clone()];
+        part
+    }
+
+    pub fn render(&self, options: &RenderOptions) -> String {
+        match options.format {
+            Format::Txt => self.to_text(options),
+            Format::Json => self.to_json(options),
+            Format::Srt => self.to_srt(),
+            Format::Vtt => self.to_vtt(options),
+            Format::Md => self.to_markdown(options),
+            Format::Html => self.to_html(options),
+        }
+    }
+
+    /// The transcript split by chapter with `--split-chapters`, or whole.
+    /// Chapters outside a `--start`/`--end` range are left out.
+    fn sections(&self, options: &RenderOptions) -> Vec<(Option<&Chapter>, Transcript)> {
+        if !options.chapters || self.chapters.is_empty() {
+            return vec![(None, self.clone())];
+        }
+        self.chapters
+            .iter()
+            .map(|chapter| (Some(chapter), self.chapter(chapter)))
+            .filter(|(_, part)| !part.text.is_empty())
+            .collect()
+    }
+
+    /// Groups segments into paragraphs, starting a new one after a pause
+    /// longer than `gap` seconds or when the speaker changes.
+    fn paragraphs(&self, gap: f64) -> Vec<Paragraph> {
+        if self.segments.is_empty() {
+            return vec![Paragraph {
+                start: 0.0,
+                speaker: None,
+                text: self.text.trim().to_string(),
+            }];
+        }
+
+        let mut paragraphs: Vec<Paragraph> = Vec::new();
+        let mut last_end = f64::NEG_INFINITY;
+        for s in &self.segments {
+            let text = s.text.trim();
+            match paragraphs.last_mut() {
+                Some(paragraph) if s.start - last_end <= gap && paragraph.speaker == s.speaker => {
+                    paragraph.text.push(' ');
+                    paragraph.text.push_str(text);
+                }
+                _ => paragraphs.push(Paragraph {
+                    start: s.start,
+                    speaker: s.speaker.clone(),
+                    text: text.to_string(),
+                }),
+            }
+            last_end = s.end;
+        }
+        paragraphs
+    }
+
+    fn has_speakers(&self) -> bool {
+        self.segments.iter().any(|s| s.speaker.is_some())
+    }
+
+    /// `Key: value` metadata lines: the source with `--with-metadata`, and
+    /// the audio filter chain whenever there is one.
+    fn header_lines(&self, options: &RenderOptions) -> Vec<String> {
+        let mut lines = match self.source.as_ref().filter(|_| options.metadata) {
+            Some(source) => source.lines(),
+            None => Vec::new(),
+        };
+        if let Some(filters) = &self.audio_filters {
+            lines.push(format!("Audio filters: {}", filters));
+        }
+        lines
+    }
+
+    /// Metadata lines above the text and in the WebVTT note.
+    fn text_header(&self, options: &RenderOptions) -> String {
+        let lines = self.header_lines(options);
+        if lines.is_empty() {
+            return String::new();
+        }
+        format!("{}\n\n", lines.join("\n"))
+    }
+
+    /// YAML front matter for Markdown output, with the same fields as
+    /// [`Transcript::header_lines`]. JSON strings are valid YAML, so values
+    /// are quoted with serde_json.
+    fn front_matter(&self, options: &RenderOptions) -> String {
+        let source = self.source.as_ref().filter(|_| options.metadata);
+        if source.is_none() && self.audio_filters.is_none() {
+            return String::new();
+        }
+        let mut out = String::from("---\n");
+        let mut field = |key: &str, value: Value| {
+            if !value.is_null() {
+                out.push_str(&format!("{}: {}\n", key, value));
+            }
+        };
+        if let Some(source) = source {
+            field("title", source.title.clone().into());
+            field("uploader", source.uploader.clone().into());
+            field("upload_date", source.upload_date.clone().into());
+            field("duration", source.duration.into());
+            field("id", source.id.clone().into());
+            field("url", source.url.clone().into());
+            field("backend", source.backend.clone().into());
+            field("model", source.model.clone().into());
+        }
+        field("audio_filters", self.audio_filters.clone().into());
+        out.push_str("---\n\n");
+        out
+    }
+
+    fn to_text(&self, options: &RenderOptions) -> String {
+        let mut out = self.text_header(options);
+        for (chapter, part) in self.sections(options) {
+            if let Some(chapter) = chapter {
+                let heading = format!("{} [{}]", chapter.title, clock(chapter.start));
+                out.push_str(&format!(
+                    "{}\n{}\n\n",
+                    heading,
+                    "-".repeat(heading.chars().count())
+                ));
+            }
+            if part.has_speakers() {
+                for paragraph in part.paragraphs(f64::INFINITY) {
+                    out.push_str(&format!("{}{}\n\n", paragraph.label(), paragraph.text));
+                }
+            } else {
+                out.push_str(&format!("{}\n\n", part.text.trim()));
+            }
+        }
+        out.trim_end().to_string()
+    }
+
+    fn to_json(&self, options: &RenderOptions) -> String {
+        if options.metadata {
+            return serde_json::to_string_pretty(self).unwrap_or_default();
+        }
+        let without_source = Transcript {
+            source: None,
+            ..self.clone()
+        };
+        serde_json::to_string_pretty(&without_source).unwrap_or_default()
+    }
+
+    fn to_srt(&self) -> String {
+        // SubRip has no room for a header, so metadata such as the audio
+        // filter chain is left out
+        let mut out = String::new();
+        for (i, s) in self.segments.iter().enumerate() {
+            out.push_str(&format!(
+                "{}\n{} --> {}\n{}{}\n\n",
+                i + 1,
+                timestamp(s.start, ','),
+                timestamp(s.end, ','),
+                s.speaker
+                    .as_ref()
+                    .map(|speaker| format!("{}: ", speaker))
+                    .unwrap_or_default(),
+                s.text.trim()
+            ));
+        }
+        out
+    }
+
+    fn to_vtt(&self, options: &RenderOptions) -> String {
+        let mut out = String::from("WEBVTT\n\n");
+        let header = self.text_header(options);
+        if !header.is_empty() {
+            out.push_str(&format!("NOTE\n{}", header));
+        }
+        for s in &self.segments {
+            out.push_str(&format!(
+                "{} --> {}\n{}{}\n\n",
+                timestamp(s.start, '.'),
+                timestamp(s.end, '.'),
+                // WebVTT voice spans let players show the speaker
+                s.speaker
+                    .as_ref()
+                    .map(|speaker| format!("<v {}>", speaker))
+                    .unwrap_or_default(),
+                s.text.trim()
+            ));
+        }
+        out
+    }
+
+    fn to_markdown(&self, options: &RenderOptions) -> String {
+        let mut out = self.front_matter(options);
+        for (chapter, part) in self.sections(options) {
+            if let Some(chapter) = chapter {
+                out.push_str(&format!("## {}\n\n", chapter.title));
+            }
+            for paragraph in part.paragraphs(options.paragraph_gap) {
+                let stamp = match self.link(paragraph.start) {
+                    Some(link) => format!("[[{}]]({})", clock(paragraph.start), link),
+                    None => format!("[{}]", clock(paragraph.start)),
+                };
+                let speaker = paragraph
+                    .speaker
+                    .as_ref()
+                    .map(|speaker| format!("**{}:** ", speaker))
+                    .unwrap_or_default();
+                out.push_str(&format!("{} {}{}\n\n", stamp, speaker, paragraph.text));
+            }
+        }
+        out.trim_end().to_string() + "\n"
+    }
+
+    fn to_html(&self, options: &RenderOptions) -> String {
+        let title = self
+            .source
+            .as_ref()
+            .map(|source| source.title.as_str())
+            .unwrap_or("Transcript");
+
+        let mut body = String::new();
+        let header = self.header_lines(options);
+        if !header.is_empty() {
+            body.push_str("<dl class=\"meta\">\n");
+            for line in header {
+                if let Some((key, value)) = line.split_once(": ") {
+                    body.push_str(&format!(
+                        "<dt>{}</dt><dd>{}</dd>\n",
+                        escape_html(key),
+                        escape_html(value)
+                    ));
+                }
+            }
+            body.push_str("</dl>\n");
+        }
+        for (chapter, part) in self.sections(options) {
+            if let Some(chapter) = chapter {
+                body.push_str(&format!("<h2>{}</h2>\n", escape_html(&chapter.title)));
+            }
+            for paragraph in part.paragraphs(options.paragraph_gap) {
+                let stamp = match self.link(paragraph.start) {
+                    Some(link) => format!(
+                        "<a class=\"ts\" href=\"{}\">[{}]</a>",
+                        escape_html(&link),
+                        clock(paragraph.start)
+                    ),
+                    None => format!("<span class=\"ts\">[{}]</span>", clock(paragraph.start)),
+                };
+                let speaker = paragraph
+                    .speaker
+                    .as_ref()
+                    .map(|speaker| format!("<b class=\"speaker\">{}:</b>", escape_html(speaker)))
+                    .unwrap_or_default();
+                body.push_str(&format!(
+                    "<p>{}{} {}</p>\n",
+                    stamp,
+                    speaker,
+                    escape_html(&paragraph.text)
+                ));
+            }
+        }
+
+        HTML_TEMPLATE
+            .replace("{title}", &escape_html(title))
+            .replace("{body}", &body)
+    }
+
+    fn link(&self, seconds: f64) -> Option<String> {
+        self.source.as_ref()?.link(seconds)
+    }
+}
+
+/// Consecutive segments shown as one block in text, Markdown and HTML.
+struct Paragraph {
+    start: f64,
+    speaker: Option<String>,
+    text: String,
+}
+
+impl Paragraph {
+    /// `Speaker 1: ` for diarized transcripts.
+    fn label(&self) -> String {
+        self.speaker
+            .as_ref()
+            .map(|speaker| format!("{}: ", speaker))
+            .unwrap_or_default()
+    }
+}
+
+/// Page around the HTML transcript. Styles and the search script are inline
+/// so the file works on its own.
+const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+body { font: 17px/1.6 system-ui, sans-serif; max-width: 46em; margin: 2em auto; padding: 0 1em; color: #222; }
+#search { width: 100%; padding: .5em; font-size: 1em; box-sizing: border-box; position: sticky; top: 0; }
+#count { color: #666; font-size: .9em; }
+.speaker { margin-right: .3em; }
+.ts { color: #666; font-variant-numeric: tabular-nums; text-decoration: none; margin-right: .3em; }
+.meta { display: grid; grid-template-columns: max-content 1fr; gap: 0 1em; color: #444; }
+.meta dt { font-weight: bold; }
+.meta dd { margin: 0; }
+mark { background: #ffe066; }
+.hidden { display: none; }
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<input id="search" type="search" placeholder="Search the transcript" autofocus>
+<p id="count"></p>
+<main>
+{body}</main>
+<script>
+const paragraphs = [...document.querySelectorAll("main p")];
+for (const p of paragraphs) p.dataset.text = p.lastChild.textContent;
+const escape = s => s.replace(/[&<>"]/g, c => ({"&": "&amp;", "<": "&lt;", ">": "&gt;", '"': "&quot;"})[c]);
+document.getElementById("search").addEventListener("input", event => {
+  const query = event.target.value.trim().toLowerCase();
+  let matches = 0;
+  for (const p of paragraphs) {
+    const text = p.dataset.text;
+    const at = query ? text.toLowerCase().indexOf(query) : -1;
+    p.classList.toggle("hidden", Boolean(query) && at < 0);
+    if (at < 0) {
+      p.lastChild.replaceWith(document.createTextNode(text));
+      continue;
+    }
+    matches++;
+    const span = document.createElement("span");
+    span.innerHTML = escape(text.slice(0, at)) + "<mark>" + escape(text.slice(at, at + query.length)) + "</mark>" + escape(text.slice(at + query.length));
+    p.lastChild.replaceWith(span);
+  }
+  document.getElementById("count").textContent = query ? matches + " matching paragraphs" : "";
+});
+</script>
+</body>
+</html>
+"#;
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Formats seconds as `HH:MM:SS<sep>mmm`. SubRip uses a comma before the
+/// milliseconds, WebVTT a period.
+fn timestamp(seconds: f64, separator: char) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+/// Formats seconds as `MM:SS`, or `H:MM:SS` from an hour on.
+fn clock(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    match seconds / 3600 {
+        0 => format!("{:02}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: f64, end: f64, text: &str) -> Segment {
+        Segment {
+            start,
+            end,
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn chunk(text: &str, segments: Vec<Segment>) -> Transcript {
+        Transcript {
+            text: text.to_string(),
+            segments,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn append_keeps_boundary_segments_once() {
+        // The first chunk runs to 65 s and the second starts at the 60 s cut,
+        // so both transcribe the sentence spoken from 55 s to 63 s
+        let mut transcript = Transcript::default();
+        transcript.append(
+            chunk(
+                "Welcome. Here is a sentence across the cut.",
+                vec![
+                    segment(0.0, 30.0, "Welcome."),
+                    segment(55.0, 63.0, "Here is a sentence across the cut."),
+                ],
+            ),
+            0.0,
+            Some(60.0),
+        );
+        transcript.append(
+            chunk(
+                "sentence across the cut. And the rest.",
+                vec![
+                    segment(0.0, 3.0, "sentence across the cut."),
+                    segment(3.5, 9.0, "And the rest."),
+                ],
+            ),
+            60.0,
+            None,
+        );
+
+        let texts: Vec<_> = transcript
+            .segments
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "Welcome.",
+                "Here is a sentence across the cut.",
+                "And the rest."
+            ]
+        );
+        assert_eq!(transcript.segments[2].start, 63.5);
+        assert_eq!(
+            transcript.text,
+            "Welcome. Here is a sentence across the cut. And the rest."
+        );
+    }
+
+    #[test]
+    fn append_drops_segments_after_the_cutoff() {
+        let mut transcript = Transcript::default();
+        transcript.append(
+            chunk(
+                "One. Two.",
+                vec![segment(0.0, 5.0, "One."), segment(61.0, 64.0, "Two.")],
+            ),
+            0.0,
+            Some(60.0),
+        );
+        assert_eq!(transcript.segments.len(), 1);
+    }
+
+    #[test]
+    fn timestamps_use_the_format_separator() {
+        assert_eq!(timestamp(0.0, ','), "00:00:00,000");
+        assert_eq!(timestamp(61.5, ','), "00:01:01,500");
+        assert_eq!(timestamp(3723.004, '.'), "01:02:03.004");
+    }
+
+    #[test]
+    fn timestamps_round_to_milliseconds() {
+        assert_eq!(timestamp(59.9996, '.'), "00:01:00.000");
+        assert_eq!(timestamp(1.0004, '.'), "00:00:01.000");
+        assert_eq!(timestamp(-0.2, ','), "00:00:00,000");
+        assert_eq!(timestamp(360_000.0, ','), "100:00:00,000");
+    }
+
+    #[test]
+    fn audio_filters_are_recorded_without_metadata() {
+        let transcript = Transcript {
+            text: "Hello.".to_string(),
+            segments: vec![segment(0.0, 1.0, "Hello.")],
+            audio_filters: Some("loudnorm".to_string()),
+            ..Default::default()
+        };
+        let render = |format| {
+            transcript.render(&RenderOptions {
+                format,
+                chapters: false,
+                metadata: false,
+                paragraph_gap: 2.0,
+            })
+        };
+        assert_eq!(render(Format::Txt), "Audio filters: loudnorm\n\nHello.");
+        assert!(render(Format::Vtt).starts_with("WEBVTT\n\nNOTE\nAudio filters: loudnorm\n\n"));
+        assert!(render(Format::Json).contains(r#""audio_filters": "loudnorm""#));
+    }
+
+    #[test]
+    fn shift_keeps_the_duration() {
+        let mut transcript = chunk("Hello.", vec![segment(1.0, 2.0, "Hello.")]);
+        transcript.duration = Some(1680.0);
+        transcript.shift(720.0);
+        assert_eq!(transcript.segments[0].start, 721.0);
+        assert_eq!(transcript.duration, Some(1680.0));
+    }
+
+    #[test]
+    fn append_extends_the_duration() {
+        let mut transcript = Transcript::default();
+        let mut part = chunk("Hello.", vec![segment(1.0, 2.0, "Hello.")]);
+        part.duration = Some(65.0);
+        transcript.append(part.clone(), 0.0, Some(60.0));
+        transcript.append(part, 60.0, None);
+        assert_eq!(transcript.duration, Some(125.0));
+    }
+
+    fn with_source() -> Transcript {
+        Transcript {
+            text: "Hello.".to_string(),
+            segments: vec![segment(65.0, 66.0, "Hello.")],
+            source: Some(Metadata {
+                title: "A \"quoted\" talk".to_string(),
+                uploader: Some("Someone".to_string()),
+                upload_date: Some("2024-03-01".to_string()),
+                duration: Some(3725.0),
+                id: "abc".to_string(),
+                url: Some("https://www.youtube.com/watch?v=abc".to_string()),
+                backend: "groq".to_string(),
+                model: "whisper-large-v3".to_string(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn render(transcript: &Transcript, format: Format, metadata: bool) -> String {
+        transcript.render(&RenderOptions {
+            format,
+            chapters: false,
+            metadata,
+            paragraph_gap: 2.0,
+        })
+    }
+
+    #[test]
+    fn metadata_is_only_shown_when_asked_for() {
+        let transcript = with_source();
+        assert_eq!(render(&transcript, Format::Txt, false), "Hello.");
+        assert!(!render(&transcript, Format::Json, false).contains("\"source\""));
+        assert!(render(&transcript, Format::Md, false).starts_with("[[01:05]]"));
+    }
+
+    #[test]
+    fn metadata_headers() {
+        let transcript = with_source();
+        let header = "Title: A \"quoted\" talk\n\
+                      Uploader: Someone\n\
+                      Upload date: 2024-03-01\n\
+                      Duration: 1:02:05\n\
+                      Video ID: abc\n\
+                      URL: https://www.youtube.com/watch?v=abc\n\
+                      Transcribed with: groq (whisper-large-v3)\n\n";
+        assert_eq!(
+            render(&transcript, Format::Txt, true),
+            format!("{}Hello.", header)
+        );
+        assert!(render(&transcript, Format::Vtt, true)
+            .starts_with(&format!("WEBVTT\n\nNOTE\n{}", header)));
+        assert!(render(&transcript, Format::Json, true).contains(r#""uploader": "Someone""#));
+    }
+
+    #[test]
+    fn markdown_front_matter() {
+        let markdown = render(&with_source(), Format::Md, true);
+        assert_eq!(
+            markdown,
+            "---\n\
+             title: \"A \\\"quoted\\\" talk\"\n\
+             uploader: \"Someone\"\n\
+             upload_date: \"2024-03-01\"\n\
+             duration: 3725.0\n\
+             id: \"abc\"\n\
+             url: \"https://www.youtube.com/watch?v=abc\"\n\
+             backend: \"groq\"\n\
+             model: \"whisper-large-v3\"\n\
+             ---\n\n\
+             [[01:05]](https://youtu.be/abc?t=65) Hello.\n"
+        );
+    }
+
+    #[test]
+    fn markdown_and_html_record_audio_filters() {
+        let mut transcript = with_source();
+        transcript.audio_filters = Some("highpass=f=80,loudnorm".to_string());
+
+        let markdown = render(&transcript, Format::Md, true);
+        assert!(markdown.contains(
+            "model: \"whisper-large-v3\"\naudio_filters: \"highpass=f=80,loudnorm\"\n---\n"
+        ));
+        let markdown = render(&transcript, Format::Md, false);
+        assert!(markdown
+            .starts_with("---\naudio_filters: \"highpass=f=80,loudnorm\"\n---\n\n[[01:05]]"));
+
+        let html = render(&transcript, Format::Html, false);
+        assert!(html.contains(
+            "<dl class=\"meta\">\n<dt>Audio filters</dt><dd>highpass=f=80,loudnorm</dd>\n</dl>"
+        ));
+        assert!(!html.contains("<dt>Title</dt>"));
+        assert!(render(&transcript, Format::Html, true)
+            .contains("<dt>Title</dt><dd>A &quot;quoted&quot; talk</dd>"));
+    }
+}